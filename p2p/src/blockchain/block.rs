@@ -1,11 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-use crate::merkle::MerkleTree;
+use crate::merkle::{MerkleProof, MerkleTree};
 
-use super::{BlockHeader, DoubleHasher, HashFunc, Transaction};
+use crate::kademlia::NODE_ID_LENGTH;
+
+use super::{
+    block_header::{BLOCK_VERSION, LEGACY_BLOCK_VERSION},
+    BlockHeader, DoubleHasher, HashFunc, Transaction,
+};
 
 type MerkleRoot = [u8; 32];
 type Hash = [u8; 32];
+type PublicKey = [u8; NODE_ID_LENGTH];
 
 pub const MAX_TRANSACTION: usize = 200;
 
@@ -21,29 +27,35 @@ impl Block {
         difficulty: u32,
         merkle_root: MerkleRoot,
         prev_hash: Hash,
-        hash: Hash,
         timestamp: u128,
         nonce: u32,
+        miner: PublicKey,
         transactions: Vec<Transaction>,
     ) -> Block {
         Block {
             header: BlockHeader {
+                version: BLOCK_VERSION,
                 index,
                 difficulty,
                 merkle_root,
                 prev_hash,
-                hash,
+                hash: [0u8; 32],
                 timestamp,
                 nonce,
+                miner,
                 signature: None,
+                state_root: [0u8; 32],
             },
             transactions,
         }
     }
 
     pub(crate) fn new_genesis() -> Block {
+        // the genesis keeps the legacy encoding so its hash is shared by
+        // chains persisted before the header was versioned
         let mut block = Block {
             header: BlockHeader {
+                version: LEGACY_BLOCK_VERSION,
                 index: 0,
                 difficulty: 0,
                 timestamp: 0,
@@ -51,7 +63,9 @@ impl Block {
                 nonce: 0,
                 prev_hash: [0u8; 32],
                 hash: [0u8; 32],
+                miner: [0u8; NODE_ID_LENGTH],
                 signature: None,
+                state_root: [0u8; 32],
             },
             transactions: vec![],
         };
@@ -61,27 +75,31 @@ impl Block {
     }
 
     fn compute_hash<THasher: HashFunc>(&self, hasher: THasher) -> [u8; 32] {
-        hasher.hash(format!(
-            "{}{}{}{}",
-            hex::encode(self.header.prev_hash),
-            hex::encode(self.header.merkle_root),
-            self.header.timestamp,
-            self.header.nonce
-        ))
+        self.header.compute_hash(hasher)
     }
 
     pub fn validate<THasher>(&self, hasher: THasher, merkle_root: MerkleRoot) -> bool
     where
         THasher: HashFunc,
     {
-        let merkle_tree = MerkleTree::from_transactions(self.transactions.clone());
-
-        if merkle_tree.root != merkle_root {
+        if self.merkle_tree().root != merkle_root {
             return false;
         }
 
-        let compute_hash = self.compute_hash(hasher);
-        compute_hash == self.header.hash
+        self.compute_hash(hasher) == self.header.hash
+    }
+
+    /// Inclusion proof of the transaction at `index` against the header's merkle root.
+    pub fn transaction_proof(&self, index: usize) -> Option<MerkleProof> {
+        self.merkle_tree().proof(index)
+    }
+
+    fn merkle_tree(&self) -> MerkleTree {
+        if self.header.version == LEGACY_BLOCK_VERSION {
+            return MerkleTree::from_transactions_legacy(self.transactions.clone());
+        }
+
+        MerkleTree::from_transactions(self.transactions.clone())
     }
 
     pub fn get_transaction<TData: 'static>(&self) -> impl Iterator<Item = (&Transaction, &TData)> {
@@ -1,15 +1,58 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use super::{hash_func::HashFunc, Block, Transaction};
-use crate::{kademlia::secret_key::SecretPair, merkle::MerkleTree};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use log::info;
+use thiserror::Error;
+
+use super::{
+    block_header::current_timestamp, hash_func::HashFunc, pow, pow_workers::PowWorkers, Block,
+    Transaction, WorldState,
+};
+use crate::{
+    kademlia::{secret_key::SecretPair, NODE_ID_LENGTH},
+    merkle::MerkleTree,
+};
+
+type PublicKey = [u8; NODE_ID_LENGTH];
+
+/// Longest sleep before checking whether a scheduled block was cancelled.
+const SCHEDULE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Error)]
+pub enum MiningError {
+    #[error("Missing miner keys")]
+    MissingKeys,
+
+    #[error("Mining was cancelled")]
+    Cancelled,
+
+    #[error("Miner isn't eligible for the next block")]
+    NotEligible,
+
+    #[error("Transaction {id} can't be applied to the state", id = hex::encode(.0))]
+    RejectedTransaction([u8; 32]),
+}
 
+/// Template of a block, sealed by the consensus engine.
 #[derive(Debug)]
-pub(crate) struct BlockBuilder {
+pub struct BlockBuilder {
     index: u64,
     difficulty: u32,
     prev_hash: [u8; 32],
     transactions: Vec<Transaction>,
     pair: Option<SecretPair>,
+
+    /// Timestamp and nonce fixed by the consensus engine, if it doesn't search them.
+    schedule: Option<(u128, u32)>,
+
+    /// Earliest timestamp the block is valid at.
+    min_timestamp: u128,
+
+    /// State of the parent, the block commits to it once its transactions are applied.
+    state: WorldState,
 }
 
 impl BlockBuilder {
@@ -20,9 +63,20 @@ impl BlockBuilder {
             prev_hash,
             transactions: vec![],
             pair: None,
+            schedule: None,
+            min_timestamp: 0,
+            state: WorldState::default(),
         }
     }
 
+    pub fn prev_hash(&self) -> [u8; 32] {
+        self.prev_hash
+    }
+
+    pub fn miner(&self) -> Option<PublicKey> {
+        self.pair.as_ref().map(|pair| pair.public_key)
+    }
+
     pub fn add_transactions<Iterator>(&mut self, transactions: Iterator) -> &mut Self
     where
         Iterator: IntoIterator<Item = Transaction>,
@@ -31,76 +85,128 @@ impl BlockBuilder {
         self
     }
 
+    pub fn retain_transactions<Predicate>(&mut self, predicate: Predicate) -> &mut Self
+    where
+        Predicate: FnMut(&Transaction) -> bool,
+    {
+        self.transactions.retain(predicate);
+        self
+    }
+
     pub fn sign_with(&mut self, pair: SecretPair) -> &mut Self {
         self.pair = Some(pair);
         self
     }
 
-    pub fn mine<THasher: HashFunc>(&self, hasher: THasher) -> Block {
-        let mut hash: [u8; 32];
-        let mut nonce = 0;
+    pub fn min_timestamp(&mut self, timestamp: u128) -> &mut Self {
+        self.min_timestamp = timestamp;
+        self
+    }
 
-        // compute the merkle tree
-        let merkle_tree = MerkleTree::from_transactions(self.transactions.clone());
-        let merkle_root = merkle_tree.root;
+    pub fn on_state(&mut self, state: WorldState) -> &mut Self {
+        self.state = state;
+        self
+    }
 
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Failed to calculate the timestamp")
-            .as_nanos();
+    pub fn schedule(&mut self, timestamp: u128, nonce: u32) -> &mut Self {
+        self.schedule = Some((timestamp, nonce));
+        self
+    }
+
+    /// Waits for the scheduled timestamp, then signs the block with the
+    /// scheduled nonce, unless `cancelled` is set meanwhile.
+    pub fn seal_scheduled<THasher: HashFunc>(
+        &self,
+        hasher: THasher,
+        cancelled: &AtomicBool,
+    ) -> Result<Block, MiningError> {
+        let (timestamp, nonce) = self.schedule.ok_or(MiningError::NotEligible)?;
+        let (mut block, pair) = self.template(timestamp)?;
 
         loop {
-            let input = format!(
-                "{}{}{}{}",
-                hex::encode(self.prev_hash),
-                hex::encode(merkle_root),
-                timestamp,
-                nonce
-            );
-
-            hash = hasher.hash(input);
-
-            if self.validate_hash(&hash, self.difficulty) {
-                let mut block = Block::new(
-                    self.index,
-                    self.difficulty,
-                    merkle_root,
-                    self.prev_hash,
-                    hash,
-                    timestamp,
-                    nonce,
-                    self.transactions.clone(),
-                );
-
-                let Some(pair) = self.pair.clone() else {
-                    continue;
-                };
-
-                block.header.sign(pair);
-                return block;
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(MiningError::Cancelled);
             }
 
-            nonce = nonce.wrapping_add(1);
+            let Some(remaining) = timestamp.checked_sub(current_timestamp()) else {
+                break;
+            };
+
+            let remaining = Duration::from_nanos(u64::try_from(remaining).unwrap_or(u64::MAX));
+            thread::sleep(remaining.min(SCHEDULE_POLL));
         }
+
+        block.header.nonce = nonce;
+        block.header.hash = block.header.compute_hash(hasher);
+        block.header.sign(pair);
+
+        Ok(block)
     }
 
-    fn validate_hash(&self, hash: &[u8; 32], difficulty: u32) -> bool {
-        let nibbles = difficulty as usize;
-        let full_bytes = nibbles / 2;
-        let has_half_nibble = nibbles % 2 == 1;
+    /// Searches a nonce on `workers` until the block meets its difficulty or
+    /// `cancelled` is set, moving the timestamp forward whenever every nonce failed.
+    pub fn mine<THasher: HashFunc + Sync>(
+        &self,
+        hasher: THasher,
+        workers: &PowWorkers,
+        cancelled: &AtomicBool,
+    ) -> Result<Block, MiningError> {
+        let (mut block, pair) = self.template(current_timestamp().max(self.min_timestamp))?;
 
-        for i in 0..full_bytes {
-            if hash[i] != 0 {
-                return false;
-            }
-        }
+        loop {
+            let header = &block.header;
+            let found = workers.search(cancelled, |nonce| {
+                let mut header = header.clone();
+                header.nonce = nonce;
 
-        if has_half_nibble {
-            if (hash[full_bytes] >> 4) != 0 {
-                return false;
+                let hash = header.compute_hash(hasher.clone());
+                pow::validate_hash(&hash, self.difficulty).then_some(hash)
+            });
+
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(MiningError::Cancelled);
             }
+
+            let Some((nonce, hash)) = found else {
+                info!("[⛏️] Nonce space exhausted, bumping the timestamp");
+                // the state may depend on the timestamp, so its root is computed again
+                let timestamp = current_timestamp().max(block.header.timestamp + 1);
+                (block, _) = self.template(timestamp)?;
+                continue;
+            };
+
+            block.header.nonce = nonce;
+            block.header.hash = hash;
+            block.header.sign(pair);
+
+            return Ok(block);
         }
+    }
+
+    /// Unsealed block at `timestamp`, along with the keys to sign it.
+    fn template(&self, timestamp: u128) -> Result<(Block, SecretPair), MiningError> {
+        // blocks must identify their miner, so an unsigned block is never mined
+        let pair = self.pair.clone().ok_or(MiningError::MissingKeys)?;
+
+        // compute the merkle tree
+        let merkle_tree = MerkleTree::from_transactions(self.transactions.clone());
 
-        true
+        let mut block = Block::new(
+            self.index,
+            self.difficulty,
+            merkle_tree.root,
+            self.prev_hash,
+            timestamp,
+            0,
+            pair.public_key,
+            self.transactions.clone(),
+        );
+
+        block.header.state_root = self
+            .state
+            .root_after(&block)
+            .map_err(|(id, _)| MiningError::RejectedTransaction(id))?;
+
+        Ok((block, pair))
     }
 }
@@ -1,4 +1,7 @@
-use std::fmt;
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -8,11 +11,36 @@ use crate::kademlia::{
     NODE_ID_LENGTH,
 };
 
+use super::{validate_hash, HashFunc};
+
 type MerkleRoot = [u8; 32];
 type Hash = [u8; 32];
+type PublicKey = [u8; NODE_ID_LENGTH];
+
+/// Headers hashed from a formatted string, before the version field existed.
+/// They only cover `prev_hash`, `merkle_root`, `timestamp` and `nonce`.
+pub const LEGACY_BLOCK_VERSION: u32 = 0;
+
+/// Headers hashed from [`BlockHeader::encode`], covering every consensus field
+/// and the resulting world state. Unlike legacy blocks, their merkle root is over
+/// the canonical transaction encoding, their timestamp must exceed the median of
+/// the previous blocks and their transactions follow the nonce sequence of their
+/// sender and must pass [`TransactionData::validate`].
+///
+/// [`TransactionData::validate`]: super::TransactionData::validate
+pub const BLOCK_VERSION: u32 = 1;
+
+/// Nanoseconds since the Unix epoch, the unit of header timestamps.
+pub(crate) fn current_timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to calculate the timestamp")
+        .as_nanos()
+}
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct BlockHeader {
+    pub version: u32,
     pub index: u64,
     pub difficulty: u32,
     pub timestamp: u128,
@@ -20,7 +48,13 @@ pub struct BlockHeader {
     pub nonce: u32,
     pub prev_hash: Hash,
     pub hash: Hash,
+    pub miner: PublicKey,
     pub signature: Option<Signature>,
+
+    /// Root of [`WorldState`] once the block is applied, zeroed in legacy blocks.
+    ///
+    /// [`WorldState`]: super::WorldState
+    pub state_root: Hash,
 }
 
 impl BlockHeader {
@@ -35,6 +69,46 @@ impl BlockHeader {
 
         signature.validate_signature(pub_key, self.hash)
     }
+
+    pub fn validate_miner_signature(&self) -> bool {
+        self.validate_signature(self.miner)
+    }
+
+    pub fn meets_difficulty(&self) -> bool {
+        validate_hash(&self.hash, self.difficulty)
+    }
+
+    /// Canonical little-endian encoding of the consensus fields, i.e. everything
+    /// but the `hash` and the `signature` made over it.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 8 + 4 + 16 + 32 + 4 + 32 + NODE_ID_LENGTH + 32);
+
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.difficulty.to_le_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&self.merkle_root);
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes.extend_from_slice(&self.prev_hash);
+        bytes.extend_from_slice(&self.miner);
+        bytes.extend_from_slice(&self.state_root);
+
+        bytes
+    }
+
+    pub fn compute_hash<THasher: HashFunc>(&self, hasher: THasher) -> Hash {
+        if self.version == LEGACY_BLOCK_VERSION {
+            return hasher.hash(format!(
+                "{}{}{}{}",
+                hex::encode(self.prev_hash),
+                hex::encode(self.merkle_root),
+                self.timestamp,
+                self.nonce
+            ));
+        }
+
+        hasher.hash_bytes(&self.encode())
+    }
 }
 
 impl fmt::Debug for BlockHeader {
@@ -42,13 +116,16 @@ impl fmt::Debug for BlockHeader {
         let mut debug = f.debug_struct("Header");
 
         debug
+            .field("version", &self.version)
             .field("index", &self.index)
             .field("difficulty", &self.difficulty)
             .field("timestamp", &self.timestamp)
             .field("merkle_root", &hex::encode(&self.merkle_root))
             .field("nonce", &self.nonce)
             .field("prev_hash", &hex::encode(&self.prev_hash))
-            .field("hash", &hex::encode(self.hash));
+            .field("hash", &hex::encode(self.hash))
+            .field("miner", &hex::encode(self.miner))
+            .field("state_root", &hex::encode(self.state_root));
 
         if let Some(signature) = &self.signature {
             debug.field("signature", &signature);
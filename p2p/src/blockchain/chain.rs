@@ -1,4 +1,11 @@
-use std::{sync::Arc, time};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time,
+};
 
 use log::{error, info};
 use serde::{Deserialize, Serialize};
@@ -8,10 +15,25 @@ use tokio::sync::Mutex;
 use crate::{blockchain::event::BlockChainEvent, kademlia::secret_key::SecretPair};
 
 use super::{
-    block_builder::BlockBuilder, event::BlockChainEventHandler, hash_func::DoubleHasher,
-    transaction_pool::TransactionPool, Block, HashFunc, Transaction,
+    block_builder::{BlockBuilder, MiningError},
+    block_header::current_timestamp,
+    consensus::Consensus,
+    event::BlockChainEventHandler,
+    hash_func::DoubleHasher,
+    orphan_pool::OrphanPool,
+    pow_workers::PowWorkers,
+    state::{StateMachine, WorldState},
+    transaction_pool::{TransactionPool, TransactionPoolError},
+    Block, BlockHeader, ChainParams, HashFunc, Transaction, TransactionError, BLOCK_VERSION,
+    LEGACY_BLOCK_VERSION,
 };
 
+type Hash = [u8; 32];
+type PublicKey = [u8; 32];
+
+/// Number of blocks the median time past is taken over.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
 #[derive(Debug, Error)]
 pub enum BlockChainError {
     #[error("Block already appended")]
@@ -25,51 +47,331 @@ pub enum BlockChainError {
 
     #[error("Chain is broken")]
     ChainBroken,
+
+    #[error("Block signature doesn't match its miner")]
+    InvalidSignature,
+
+    #[error("Block doesn't satisfy the expected difficulty")]
+    InvalidProofOfWork,
+
+    #[error("Transaction {id} is invalid: {1}", id = hex::encode(.0))]
+    InvalidTransaction(Hash, TransactionError),
+
+    #[error("Block doesn't extend the current tip")]
+    StaleBlock,
+
+    #[error("Block miner isn't eligible to sign it")]
+    IneligibleMiner,
+
+    #[error("Block timestamp is out of range")]
+    InvalidTimestamp,
+
+    #[error("Block conflicts with finalized history")]
+    FinalityViolation,
+
+    #[error("Block state root doesn't match the state it leads to")]
+    InvalidStateRoot,
+}
+
+impl BlockChainError {
+    /// Whether the block itself breaks a consensus rule, so whoever signed it
+    /// is to blame, see [`BlockChain::penalise_miner`].
+    pub fn is_invalid_block(&self) -> bool {
+        matches!(
+            self,
+            BlockChainError::InvalidBlock
+                | BlockChainError::InvalidProofOfWork
+                | BlockChainError::InvalidTransaction(..)
+                | BlockChainError::IneligibleMiner
+                | BlockChainError::InvalidTimestamp
+                | BlockChainError::InvalidStateRoot
+        )
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "PersistBlockChain", into = "PersistBlockChain")]
 pub struct BlockChain {
-    dificulty: u32,
+    params: ChainParams,
+    consensus: Arc<dyn Consensus>,
+
+    /// Canonical chain, ending at the tip with the most accumulated work.
     pub(crate) blocks: Vec<Block>,
 
-    #[serde(skip)]
+    /// Height of every canonical block, indexed by its hash.
+    heights: HashMap<Hash, usize>,
+
+    /// Valid blocks outside the canonical chain, indexed by their hash.
+    side_blocks: HashMap<Hash, Block>,
+
+    /// Accumulated weight, as given by the consensus engine, from the genesis
+    /// up to every known block.
+    work: HashMap<Hash, u128>,
+
+    /// Blocks whose parent isn't known yet, never persisted.
+    orphans: OrphanPool,
+
+    /// World state as of the canonical tip, rebuilt from the blocks on load.
+    state: StateMachine,
+
+    /// Set whenever the tip moves, so the block being mined on it is dropped.
+    mining_cancelled: Arc<AtomicBool>,
+
+    /// Invalid blocks received from every miner, for scoring them locally.
+    /// Every node receives different ones, so they never weigh on consensus.
+    penalties: HashMap<PublicKey, u64>,
+
     pub transaction_poll: TransactionPool,
 }
 
+/// Persisted layout of the [`BlockChain`], its indexes are rebuilt on load.
+/// Changing it, or the layout of the blocks, requires a new persist version.
+#[derive(Serialize, Deserialize)]
+struct PersistBlockChain {
+    blocks: Vec<Block>,
+    side_blocks: Vec<Block>,
+}
+
+impl From<PersistBlockChain> for BlockChain {
+    fn from(persist: PersistBlockChain) -> Self {
+        let mut block_chain = BlockChain {
+            blocks: persist.blocks,
+            side_blocks: persist
+                .side_blocks
+                .into_iter()
+                .map(|block| (block.header.hash, block))
+                .collect(),
+            ..BlockChain::new()
+        };
+
+        block_chain.index_blocks();
+        block_chain
+    }
+}
+
+impl From<BlockChain> for PersistBlockChain {
+    fn from(block_chain: BlockChain) -> Self {
+        PersistBlockChain {
+            blocks: block_chain.blocks,
+            side_blocks: block_chain.side_blocks.into_values().collect(),
+        }
+    }
+}
+
 impl BlockChain {
     pub fn new() -> BlockChain {
+        Self::with_params(ChainParams::default())
+    }
+
+    pub fn with_params(params: ChainParams) -> BlockChain {
+        let genesis = Block::new_genesis();
+        let consensus = params.consensus.engine();
+
         BlockChain {
-            dificulty: 5,
-            blocks: vec![Block::new_genesis()],
+            params,
+            work: HashMap::from([(genesis.header.hash, consensus.block_weight(&genesis.header))]),
+            consensus,
+            heights: HashMap::from([(genesis.header.hash, 0)]),
+            blocks: vec![genesis],
+            side_blocks: HashMap::new(),
+            orphans: OrphanPool::default(),
+            state: StateMachine::default(),
+            mining_cancelled: Arc::new(AtomicBool::new(false)),
+            penalties: HashMap::new(),
             transaction_poll: TransactionPool::new(),
         }
     }
 
+    /// Replaces the params along with the consensus engine they select, the
+    /// block weights are recomputed by the new engine.
+    ///
+    /// The state is rebuilt when finality moves too, undo logs pruned under the
+    /// previous params can't be brought back otherwise.
+    pub fn set_params(&mut self, params: ChainParams) {
+        let reindex = params.consensus != self.params.consensus
+            || params.finality_depth != self.params.finality_depth
+            || params.checkpoints != self.params.checkpoints;
+
+        self.consensus = params.consensus.engine();
+        self.params = params;
+
+        if reindex {
+            self.index_blocks();
+        }
+    }
+
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    pub fn consensus(&self) -> Arc<dyn Consensus> {
+        Arc::clone(&self.consensus)
+    }
+
+    pub(crate) fn from_blocks(blocks: Vec<Block>) -> BlockChain {
+        BlockChain::from_tree(blocks, vec![])
+    }
+
+    pub(crate) fn from_tree(blocks: Vec<Block>, side_blocks: Vec<Block>) -> BlockChain {
+        BlockChain::from(PersistBlockChain {
+            blocks,
+            side_blocks,
+        })
+    }
+
+    fn index_blocks(&mut self) {
+        self.work.clear();
+        self.heights.clear();
+        self.state = StateMachine::default();
+
+        let mut total_work = 0u128;
+        for (height, block) in self.blocks.iter().enumerate() {
+            total_work = total_work.saturating_add(self.consensus.block_weight(&block.header));
+            self.work.insert(block.header.hash, total_work);
+            self.heights.insert(block.header.hash, height);
+
+            // rejected blocks are caught by validate
+            let _ = self.state.apply_block(block);
+        }
+
+        self.state.prune(self.finalized_height());
+
+        // parents always sit at a lower height, so they are indexed first
+        let mut side_blocks = self.side_blocks.values().collect::<Vec<_>>();
+        side_blocks.sort_by_key(|block| block.header.index);
+
+        let mut detached = vec![];
+        for block in side_blocks {
+            let Some(parent_work) = self.work.get(&block.header.prev_hash).copied() else {
+                detached.push(block.header.hash);
+                continue;
+            };
+
+            let total_work = parent_work.saturating_add(self.consensus.block_weight(&block.header));
+            self.work.insert(block.header.hash, total_work);
+        }
+
+        for hash in detached {
+            self.side_blocks.remove(&hash);
+        }
+    }
+
     pub fn validate<THasher>(&self, hasher: THasher) -> bool
     where
         THasher: HashFunc,
     {
+        let Some(genesis) = self.blocks.first() else {
+            return false;
+        };
+
+        if !genesis.validate(hasher.clone(), genesis.header.merkle_root) {
+            return false;
+        }
+
         for (current, next) in self.blocks.iter().zip(self.blocks.iter().skip(1)) {
-            if !current.validate(hasher.clone(), current.header.merkle_root) {
+            if self.check_block(hasher.clone(), current, next).is_err() {
                 return false;
             }
+        }
 
-            if next.header.prev_hash != current.header.hash {
-                return false;
-            }
+        let mut state = StateMachine::default();
+        if self
+            .blocks
+            .iter()
+            .any(|block| Self::apply_state(&mut state, block).is_err())
+        {
+            return false;
         }
 
-        if let Some(last) = self.blocks.last() {
-            if !last.validate(hasher.clone(), last.header.merkle_root) {
-                return false;
-            }
+        self.side_blocks.values().all(|block| {
+            self.get_block_by_hash(block.header.prev_hash)
+                .is_some_and(|parent| self.check_block(hasher.clone(), parent, block).is_ok())
+        })
+    }
+
+    /// Validates `block` in the context of its `parent`, wherever it sits in the tree.
+    fn check_block<THasher>(
+        &self,
+        hasher: THasher,
+        parent: &Block,
+        block: &Block,
+    ) -> Result<(), BlockChainError>
+    where
+        THasher: HashFunc,
+    {
+        if block.header.prev_hash != parent.header.hash {
+            return Err(BlockChainError::ChainBroken);
         }
 
-        true
+        if block.header.index != parent.header.index + 1 {
+            return Err(BlockChainError::InvalidBlock);
+        }
+
+        // migrated chains may start with legacy headers, but never go back to them
+        if block.header.version < parent.header.version || block.header.version > BLOCK_VERSION {
+            return Err(BlockChainError::InvalidBlock);
+        }
+
+        if self.params.checkpoints.iter().any(|checkpoint| {
+            checkpoint.height == block.header.index && checkpoint.hash != block.header.hash
+        }) {
+            return Err(BlockChainError::FinalityViolation);
+        }
+
+        if block.header.version != LEGACY_BLOCK_VERSION
+            && block.header.timestamp <= self.median_time_past(&parent.header)
+        {
+            return Err(BlockChainError::InvalidTimestamp);
+        }
+
+        self.consensus
+            .validate_header(self, &parent.header, &block.header)?;
+
+        if !block.validate(hasher, block.header.merkle_root) {
+            return Err(BlockChainError::InvalidBlock);
+        }
+
+        // only the genesis block is allowed to be unsigned
+        if !block.header.validate_miner_signature() {
+            return Err(BlockChainError::InvalidSignature);
+        }
+
+        Self::verify_transactions(block)
+    }
+
+    /// Applies `block` to `state`, which must then match the root the block commits to.
+    fn apply_state(state: &mut StateMachine, block: &Block) -> Result<(), BlockChainError> {
+        state
+            .apply_block(block)
+            .map_err(|(id, e)| BlockChainError::InvalidTransaction(id, e))?;
+
+        if block.header.version != LEGACY_BLOCK_VERSION
+            && state.state().root() != block.header.state_root
+        {
+            state.revert_block(block);
+            return Err(BlockChainError::InvalidStateRoot);
+        }
+
+        Ok(())
+    }
+
+    fn verify_transactions(block: &Block) -> Result<(), BlockChainError> {
+        for transaction in &block.transactions {
+            let verified = if block.header.version == LEGACY_BLOCK_VERSION {
+                transaction.verify_legacy()
+            } else {
+                transaction.verify()
+            };
+
+            verified.map_err(|e| BlockChainError::InvalidTransaction(transaction.id(), e))?;
+        }
+
+        Ok(())
     }
 
     pub fn start_miner(
         pair: SecretPair,
+        workers: PowWorkers,
         block_chain: Arc<Mutex<Self>>,
         event_handler: Arc<dyn BlockChainEventHandler>,
         batch_size: usize,
@@ -81,74 +383,163 @@ impl BlockChain {
         info!("[⛏️] Miner async task started!");
 
         tokio::spawn(async move {
+            let mut restart = false;
+
             loop {
-                tokio::time::sleep(batch_pulling).await;
+                // a cancelled block is restarted on the new tip right away
+                if !std::mem::take(&mut restart) {
+                    tokio::time::sleep(batch_pulling).await;
+                }
 
                 let block_chain = Arc::clone(&block_chain);
-                let transactions = {
+                // the chain stays unlocked while mining, so blocks from the
+                // network keep being appended and cancel this one
+                let (block_builder, cancelled, consensus) = {
                     let Ok(mut block_chain) = block_chain.try_lock() else {
                         continue;
                     };
 
-                    match block_chain
+                    let transactions = match block_chain
                         .transaction_poll
                         .fetch_batch_transactions(batch_size)
                     {
-                        Ok(transactions) if !transactions.is_empty() => Some(transactions),
+                        Ok(transactions) if !transactions.is_empty() => transactions,
                         Ok(_) => {
                             info!("No transactions to be added");
-                            None
+                            continue;
                         }
                         Err(e) => {
                             error!("Failed to fetch transactions: {:?}", e);
-                            None
+                            continue;
                         }
-                    }
-                };
-
-                let Some(transactions) = transactions else {
-                    continue;
-                };
-
-                let block_chain_tx = Arc::clone(&block_chain);
-                let block = {
-                    let Ok(mut block_chain) = block_chain_tx.try_lock() else {
-                        continue;
                     };
 
-                    block_chain.add_block(|mut builder| {
+                    let (block_builder, cancelled) = block_chain.prepare_block(|mut builder| {
                         builder
                             .add_transactions(transactions)
                             .sign_with(pair.clone());
 
                         builder
+                    });
+
+                    (block_builder, cancelled, block_chain.consensus())
+                };
+
+                info!("[⛏️] Mining block on {} threads!", workers.threads());
+                let mined = {
+                    let workers = workers.clone();
+                    tokio::task::spawn_blocking(move || {
+                        consensus.seal(&block_builder, &workers, &cancelled)
                     })
+                    .await
+                };
+
+                let block = match mined {
+                    Ok(Ok(block)) => block,
+                    Ok(Err(MiningError::Cancelled)) => {
+                        info!("[⛏️] Chain tip moved, restarting miner");
+                        restart = true;
+                        continue;
+                    }
+                    Ok(Err(MiningError::NotEligible)) => {
+                        info!("[⛏️] No slot won for the next block");
+                        continue;
+                    }
+                    Ok(Err(MiningError::RejectedTransaction(id))) => {
+                        // it would be picked again for every block otherwise, and
+                        // its successors would wait on it forever
+                        info!(
+                            "[⛏️] Dropping transaction {} rejected by the state",
+                            hex::encode(id)
+                        );
+                        let _ = block_chain
+                            .lock()
+                            .await
+                            .transaction_poll
+                            .remove_with_successors(&id);
+                        restart = true;
+                        continue;
+                    }
+                    Ok(Err(e)) => {
+                        error!("Failed to mine block: {}", e);
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Miner task failed: {}", e);
+                        continue;
+                    }
                 };
 
+                info!(
+                    "[⛏️] Finish block!: {} ({:.0} H/s)",
+                    hex::encode(block.header.hash),
+                    workers.hash_rate()
+                );
+
+                // waits for the lock, a mined block is too costly to drop
+                let appended = block_chain.lock().await.append_mined_block(&block);
+                if let Err(e) = appended {
+                    info!("[⛏️] Discarding mined block: {}", e);
+                    restart = true;
+                    continue;
+                }
+
                 {
                     let event_handler = Arc::clone(&event_handler);
                     event_handler
-                        .on_event(BlockChainEvent::AddBlock(block))
+                        .on_event(BlockChainEvent::AddBlock(Box::new(block)))
                         .await;
                 }
             }
         });
     }
 
-    fn get_block_by_hash(&self, hash: [u8; 32]) -> Option<&Block> {
-        if let Some(position) = self
-            .blocks
-            .iter()
-            .position(|block| block.header.hash == hash)
-        {
-            return self.blocks.get(position);
+    /// Looks `hash` up across the whole block tree, side branches included.
+    pub fn get_block_by_hash(&self, hash: [u8; 32]) -> Option<&Block> {
+        if let Some(height) = self.heights.get(&hash) {
+            return self.blocks.get(*height);
         }
 
-        None
+        self.side_blocks.get(&hash)
+    }
+
+    /// Canonical block at `height`.
+    pub fn get_block_by_height(&self, height: u64) -> Option<&Block> {
+        self.blocks.get(usize::try_from(height).ok()?)
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.work.contains_key(hash)
+    }
+
+    fn is_canonical(&self, header: &BlockHeader) -> bool {
+        self.heights.contains_key(&header.hash)
+    }
+
+    /// Block at `height` on the branch ending at `hash`.
+    pub(crate) fn get_ancestor(&self, mut hash: Hash, height: u64) -> Option<&Block> {
+        loop {
+            let block = self.get_block_by_hash(hash)?;
+
+            if block.header.index < height {
+                return None;
+            }
+
+            if block.header.index == height {
+                return Some(block);
+            }
+
+            // below the fork point every branch shares the canonical ancestors
+            if self.is_canonical(&block.header) {
+                return self.get_block_by_height(height);
+            }
+
+            hash = block.header.prev_hash;
+        }
     }
 
     pub fn get_blockchain_head(&self) -> Option<&Block> {
-        self.blocks.iter().max_by_key(|block| block.header.index)
+        self.blocks.last()
     }
 
     pub fn search_blocks_on<PredicateFn>(
@@ -158,20 +549,7 @@ impl BlockChain {
     where
         PredicateFn: Fn(&Block) -> bool,
     {
-        let mut chain = Vec::new();
-        let tip = self
-            .get_blockchain_head()
-            .expect("Failed to find the tip of the chain");
-
-        let mut current = Some(tip);
-
-        while let Some(block) = current {
-            chain.push(block);
-            current = self.get_block_by_hash(block.header.prev_hash);
-        }
-
-        chain.reverse();
-        chain.into_iter().filter(move |block| predicate(*block))
+        self.blocks.iter().filter(move |block| predicate(*block))
     }
 
     pub fn search_transactions_on<PerdicateFn>(
@@ -187,38 +565,367 @@ impl BlockChain {
             .filter(move |transaction| perdicate(*transaction))
     }
 
-    pub fn remove_last(&mut self) -> Option<Block> {
-        self.blocks.pop()
+    /// Appends `block` to the block tree, returning the [`BlockChainEvent::Reorg`]
+    /// when its branch overtook the canonical chain.
+    ///
+    /// Blocks with an unknown parent are kept in the orphan pool and reported as
+    /// [`BlockChainError::ChainBroken`], they get connected along with their
+    /// descendants once the parent is appended.
+    pub fn append_block(
+        &mut self,
+        block: &Block,
+    ) -> Result<Option<BlockChainEvent>, BlockChainError> {
+        let old_head = self.blocks.last().map(|head| head.header.hash);
+
+        self.connect_block(block)?;
+        self.connect_orphans(block.header.hash);
+
+        Ok(old_head.and_then(|old_head| self.reorg_since(old_head)))
     }
 
-    pub fn append_block(&mut self, block: &Block) -> Result<(), BlockChainError> {
-        // TODO: verify the signature of the block
-
-        if self
-            .search_blocks_on(|b| b.header.hash == block.header.hash)
-            .next()
-            .is_some()
+    /// Records a penalty against the miner of the invalid `block`, as long as
+    /// it did sign that very block. Returns whether it was penalised.
+    pub fn penalise_miner(&mut self, block: &Block) -> bool {
+        if !block.validate(DoubleHasher::default(), block.header.merkle_root)
+            || !block.header.validate_miner_signature()
         {
+            return false;
+        }
+
+        *self.penalties.entry(block.header.miner).or_default() += 1;
+        true
+    }
+
+    /// Invalid blocks signed by `miner`, see [`BlockChain::penalise_miner`].
+    pub fn penalties(&self, miner: &PublicKey) -> u64 {
+        self.penalties.get(miner).copied().unwrap_or_default()
+    }
+
+    /// Hash of the block to request so the orphan `hash` can be connected.
+    pub fn missing_parent(&self, hash: &Hash) -> Option<Hash> {
+        self.orphans.missing_parent(hash)
+    }
+
+    fn connect_block(&mut self, block: &Block) -> Result<(), BlockChainError> {
+        if self.contains(&block.header.hash) {
             return Err(BlockChainError::BlockAlreadyPersisted);
         }
 
-        let Some(prev_block) = self.get_blockchain_head() else {
+        // legacy headers are only accepted from migrated storage, not from peers
+        if block.header.version != BLOCK_VERSION {
+            return Err(BlockChainError::InvalidBlock);
+        }
+
+        // only checked on arrival, a stored block stays valid as the clock moves
+        let max_timestamp = current_timestamp() + self.params.max_future_drift().as_nanos();
+        if block.header.timestamp > max_timestamp {
+            return Err(BlockChainError::InvalidTimestamp);
+        }
+
+        let Some(parent) = self.get_block_by_hash(block.header.prev_hash) else {
+            // only what can be checked without the parent, the rest waits for it
+            if !block.validate(DoubleHasher::default(), block.header.merkle_root) {
+                return Err(BlockChainError::InvalidBlock);
+            }
+
+            if !self.consensus.verify_seal(&block.header) {
+                return Err(BlockChainError::InvalidProofOfWork);
+            }
+
+            if !block.header.validate_miner_signature() {
+                return Err(BlockChainError::InvalidSignature);
+            }
+
+            Self::verify_transactions(block)?;
+
+            self.orphans.insert(block.clone());
+            return Err(BlockChainError::ChainBroken);
+        };
+
+        self.check_block(DoubleHasher::default(), parent, block)?;
+
+        let Some(parent_work) = self.work.get(&parent.header.hash).copied() else {
             return Err(BlockChainError::BlockNotFound);
         };
 
-        if !block.validate(DoubleHasher::default(), block.header.merkle_root) {
-            return Err(BlockChainError::InvalidBlock);
+        let is_head = self.is_head(&block.header.prev_hash);
+        let total_work = parent_work.saturating_add(self.consensus.block_weight(&block.header));
+
+        if is_head {
+            Self::apply_state(&mut self.state, block)?;
+
+            self.work.insert(block.header.hash, total_work);
+            self.push_canonical(block.clone());
+            self.state.prune(self.finalized_height());
+            self.update_transaction_pool(&[], std::slice::from_ref(block));
+
+            return Ok(());
         }
 
-        if prev_block.header.hash != block.header.prev_hash {
-            return Err(BlockChainError::ChainBroken);
+        // a branch may fork from the finalized block at most, never below it
+        if self
+            .fork_height(&block.header.prev_hash)
+            .is_none_or(|fork_height| fork_height < self.finalized_height())
+        {
+            return Err(BlockChainError::FinalityViolation);
+        }
+
+        // side branches only get their transactions applied once they carry the most work
+        let branch_state = if self.consensus.fork_choice(total_work, self.head_work()) {
+            Some(self.branch_state(block)?)
+        } else {
+            None
+        };
+
+        self.work.insert(block.header.hash, total_work);
+        self.side_blocks.insert(block.header.hash, block.clone());
+
+        if let Some(state) = branch_state {
+            let (removed, added) = self.reorganize(block.header.hash);
+            self.state = state;
+            self.state.prune(self.finalized_height());
+            self.update_transaction_pool(&removed, &added);
         }
 
-        self.blocks.push(block.clone());
         Ok(())
     }
 
-    pub(crate) fn add_block<F>(&mut self, block_builder_fn: F) -> Block
+    /// Height below which the canonical chain can't be reorganised anymore, set
+    /// by the rolling finality depth or the highest checkpoint reached.
+    pub fn finalized_height(&self) -> u64 {
+        let tip = self.next_index().saturating_sub(1);
+
+        let rolling = match self.params.finality_depth {
+            0 => 0,
+            depth => tip.saturating_sub(depth),
+        };
+
+        let checkpoint = self
+            .params
+            .checkpoints
+            .iter()
+            .filter(|checkpoint| {
+                self.get_block_by_height(checkpoint.height)
+                    .is_some_and(|block| block.header.hash == checkpoint.hash)
+            })
+            .map(|checkpoint| checkpoint.height)
+            .max()
+            .unwrap_or_default();
+
+        rolling.max(checkpoint)
+    }
+
+    /// Height of the canonical block the branch ending at `hash` forks from.
+    fn fork_height(&self, hash: &Hash) -> Option<u64> {
+        let mut fork_hash = *hash;
+        while let Some(block) = self.side_blocks.get(&fork_hash) {
+            fork_hash = block.header.prev_hash;
+        }
+
+        self.heights.get(&fork_hash).map(|height| *height as u64)
+    }
+
+    /// World state as of `tip`, a block extending a side branch.
+    fn branch_state(&self, tip: &Block) -> Result<StateMachine, BlockChainError> {
+        let mut branch = vec![tip];
+        let mut fork_hash = tip.header.prev_hash;
+
+        while let Some(block) = self.side_blocks.get(&fork_hash) {
+            fork_hash = block.header.prev_hash;
+            branch.push(block);
+        }
+
+        let Some(fork_height) = self.heights.get(&fork_hash).copied() else {
+            return Err(BlockChainError::ChainBroken);
+        };
+
+        let mut state = self.state.clone();
+        for block in self.blocks[fork_height + 1..].iter().rev() {
+            // undo logs are only pruned below the finalized height
+            if !state.revert_block(block) {
+                return Err(BlockChainError::FinalityViolation);
+            }
+        }
+
+        for block in branch.into_iter().rev() {
+            Self::apply_state(&mut state, block)?;
+        }
+
+        Ok(state)
+    }
+
+    /// Connects every orphan descending from `parent`, dropping the invalid ones.
+    fn connect_orphans(&mut self, parent: Hash) {
+        let mut parents = vec![parent];
+
+        while let Some(parent) = parents.pop() {
+            for orphan in self.orphans.take_children(&parent) {
+                match self.connect_block(&orphan) {
+                    Ok(()) => parents.push(orphan.header.hash),
+                    Err(e) => info!(
+                        "Dropping orphan block {}: {}",
+                        hex::encode(orphan.header.hash),
+                        e
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Reorg that took the canonical chain away from `old_head`, if any.
+    fn reorg_since(&self, old_head: Hash) -> Option<BlockChainEvent> {
+        let mut removed = vec![];
+        let mut hash = old_head;
+
+        loop {
+            let block = self.get_block_by_hash(hash)?;
+            if self.is_canonical(&block.header) {
+                break;
+            }
+
+            removed.push(block.clone());
+            hash = block.header.prev_hash;
+        }
+
+        removed.reverse();
+
+        let fork_height = removed.first()?.header.index as usize;
+        let added = self.blocks.get(fork_height..)?.to_vec();
+
+        Some(BlockChainEvent::Reorg { removed, added })
+    }
+
+    /// Drops the transactions mined by `added` from the pool and gives back the
+    /// ones only `removed` had included, so they can be mined again.
+    fn update_transaction_pool(&self, removed: &[Block], added: &[Block]) {
+        let included = added
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .map(|transaction| transaction.id())
+            .collect::<HashSet<_>>();
+
+        if self
+            .transaction_poll
+            .remove_transactions(&included)
+            .is_err()
+        {
+            error!("Failed to drop mined transactions from the pool");
+        }
+
+        let orphaned = removed
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .filter(|transaction| !included.contains(&transaction.id()))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if self
+            .transaction_poll
+            .restore_transactions(orphaned)
+            .is_err()
+        {
+            error!("Failed to return orphaned transactions to the pool");
+        }
+
+        if self.transaction_poll.remove_stale(self.state()).is_err() {
+            error!("Failed to drop stale transactions from the pool");
+        }
+
+        let header = self.next_header();
+        if self
+            .transaction_poll
+            .remove_rejected(self.state(), &header)
+            .is_err()
+        {
+            error!("Failed to revalidate the pool against the new state");
+        }
+
+        if self
+            .transaction_poll
+            .promote_held(self.state(), &header)
+            .is_err()
+        {
+            error!("Failed to admit the held transactions");
+        }
+    }
+
+    /// World state as of the canonical tip.
+    pub fn state(&self) -> &WorldState {
+        self.state.state()
+    }
+
+    /// Nonce the next transaction of `account` must carry, pending ones included.
+    pub fn next_nonce(&self, account: &PublicKey) -> u32 {
+        self.transaction_poll
+            .next_nonce(account)
+            .unwrap_or_else(|| self.state().next_nonce(account))
+    }
+
+    /// Admits `transaction` to the pool, checking it against the world state,
+    /// account nonces included, along with the pending transactions.
+    pub fn submit_transaction(&self, transaction: Transaction) -> Result<(), TransactionPoolError> {
+        self.transaction_poll
+            .add_transaction(transaction, self.state(), &self.next_header())
+    }
+
+    /// Makes the side branch ending at `tip` canonical, returning the blocks
+    /// that left and joined the canonical chain.
+    fn reorganize(&mut self, tip: Hash) -> (Vec<Block>, Vec<Block>) {
+        let mut added = vec![];
+        let mut fork_hash = tip;
+
+        while let Some(block) = self.side_blocks.remove(&fork_hash) {
+            fork_hash = block.header.prev_hash;
+            added.push(block);
+        }
+
+        added.reverse();
+
+        let fork_height = *self
+            .heights
+            .get(&fork_hash)
+            .expect("Side branches always fork from the canonical chain");
+
+        let removed = self.blocks.split_off(fork_height + 1);
+        for block in &removed {
+            self.heights.remove(&block.header.hash);
+            self.side_blocks.insert(block.header.hash, block.clone());
+        }
+
+        for block in &added {
+            self.push_canonical(block.clone());
+        }
+
+        (removed, added)
+    }
+
+    fn push_canonical(&mut self, block: Block) {
+        self.heights.insert(block.header.hash, self.blocks.len());
+        self.blocks.push(block);
+
+        self.mining_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_head(&self, hash: &Hash) -> bool {
+        self.blocks
+            .last()
+            .is_some_and(|block| block.header.hash == *hash)
+    }
+
+    fn head_work(&self) -> u128 {
+        self.blocks
+            .last()
+            .and_then(|block| self.work.get(&block.header.hash))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Template of the block following the tip, along with the flag cancelling
+    /// its mining once the tip moves.
+    pub(crate) fn prepare_block<F>(
+        &mut self,
+        block_builder_fn: F,
+    ) -> (BlockBuilder, Arc<AtomicBool>)
     where
         F: FnOnce(BlockBuilder) -> BlockBuilder,
     {
@@ -227,19 +934,83 @@ impl BlockChain {
             .get(self.blocks.len() - 1)
             .expect("Wasn't possible to fetch the prev block");
 
-        let block_builder = block_builder_fn(BlockBuilder::new(
+        let mut block_builder = block_builder_fn(BlockBuilder::new(
             self.next_index(),
-            self.dificulty,
+            self.expected_difficulty(&prev_block.header),
             prev_block.header.hash,
         ));
+        block_builder
+            .min_timestamp(self.median_time_past(&prev_block.header) + 1)
+            .on_state(self.state().clone());
+
+        let header = self.next_header();
+        let mut state = self.state().clone();
+        block_builder.retain_transactions(|transaction| {
+            state.apply_transaction(&header, transaction).is_ok()
+        });
+
+        self.consensus.prepare(self, &mut block_builder);
+
+        self.mining_cancelled = Arc::new(AtomicBool::new(false));
+        (block_builder, Arc::clone(&self.mining_cancelled))
+    }
+
+    /// Appends a block mined from [`BlockChain::prepare_block`], unless the tip
+    /// moved while it was mined.
+    pub(crate) fn append_mined_block(&mut self, block: &Block) -> Result<(), BlockChainError> {
+        if !self.is_head(&block.header.prev_hash) {
+            return Err(BlockChainError::StaleBlock);
+        }
+
+        self.connect_block(block)
+    }
 
-        info!("[⛏️] Mining block!");
-        let block = block_builder.mine(DoubleHasher {});
-        info!("[⛏️] Finish block!: {}", hex::encode(block.header.hash));
+    /// Median timestamp of the last [`MEDIAN_TIME_SPAN`] blocks of the branch
+    /// ending at `parent`, the block following it must be strictly later.
+    pub fn median_time_past(&self, parent: &BlockHeader) -> u128 {
+        let mut timestamps = vec![parent.timestamp];
+        let mut hash = parent.prev_hash;
 
-        self.blocks.push(block.clone());
+        while timestamps.len() < MEDIAN_TIME_SPAN {
+            let Some(block) = self.get_block_by_hash(hash) else {
+                break;
+            };
 
-        block
+            timestamps.push(block.header.timestamp);
+            hash = block.header.prev_hash;
+        }
+
+        timestamps.sort_unstable();
+        timestamps[timestamps.len() / 2]
+    }
+
+    /// Difficulty every node expects for the block following `parent`.
+    pub fn expected_difficulty(&self, parent: &BlockHeader) -> u32 {
+        self.consensus.next_difficulty(self, parent)
+    }
+
+    /// Provisional header of the block following the tip, for simulating
+    /// transactions before it's sealed.
+    fn next_header(&self) -> BlockHeader {
+        let prev_header = &self
+            .blocks
+            .last()
+            .expect("Wasn't possible to fetch the prev block")
+            .header;
+
+        // the timestamp is only known once sealed, the earliest it may get
+        // stands in for it
+        Block::new(
+            self.next_index(),
+            0,
+            [0u8; 32],
+            prev_header.hash,
+            current_timestamp().max(self.median_time_past(prev_header) + 1),
+            0,
+            [0u8; 32],
+            vec![],
+        )
+        .header
     }
 
     fn next_index(&self) -> u64 {
@@ -0,0 +1,75 @@
+use std::{
+    fmt::Debug,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    block_builder::{BlockBuilder, MiningError},
+    pow::ProofOfWork,
+    pow_workers::PowWorkers,
+    reputation::ProofOfReputation,
+    Block, BlockChain, BlockChainError, BlockHeader,
+};
+
+/// Rules a network agrees on to produce blocks and pick its canonical chain.
+///
+/// The [`BlockChain`] keeps the block tree and the transaction rules, anything
+/// specific to how blocks are sealed and weighted belongs to the engine.
+pub trait Consensus: Debug + Send + Sync {
+    /// Difficulty the block following `parent` must carry.
+    fn next_difficulty(&self, chain: &BlockChain, parent: &BlockHeader) -> u32;
+
+    /// Checks what can be told from `header` alone, before its parent is known.
+    fn verify_seal(&self, header: &BlockHeader) -> bool;
+
+    /// Checks the consensus fields of `header` against its `parent`.
+    fn validate_header(
+        &self,
+        chain: &BlockChain,
+        parent: &BlockHeader,
+        header: &BlockHeader,
+    ) -> Result<(), BlockChainError>;
+
+    /// Weight `header` adds to its branch.
+    fn block_weight(&self, header: &BlockHeader) -> u128;
+
+    /// Whether a branch of `candidate` weight replaces a canonical chain of
+    /// `canonical` weight.
+    fn fork_choice(&self, candidate: u128, canonical: u128) -> bool {
+        candidate > canonical
+    }
+
+    /// Fills in what the engine derives from the chain before sealing, called
+    /// while the chain is still locked.
+    fn prepare(&self, _chain: &BlockChain, _builder: &mut BlockBuilder) {}
+
+    /// Turns the template into a block, until `cancelled` is set.
+    fn seal(
+        &self,
+        builder: &BlockBuilder,
+        workers: &PowWorkers,
+        cancelled: &AtomicBool,
+    ) -> Result<Block, MiningError>;
+}
+
+/// Consensus engine a network runs, selected from its [`ChainParams`].
+///
+/// [`ChainParams`]: super::ChainParams
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusKind {
+    #[default]
+    ProofOfWork,
+    ProofOfReputation,
+}
+
+impl ConsensusKind {
+    pub fn engine(self) -> Arc<dyn Consensus> {
+        match self {
+            ConsensusKind::ProofOfWork => Arc::new(ProofOfWork),
+            ConsensusKind::ProofOfReputation => Arc::new(ProofOfReputation),
+        }
+    }
+}
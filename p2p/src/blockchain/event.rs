@@ -6,7 +6,14 @@ use super::Block;
 
 #[derive(Debug)]
 pub enum BlockChainEvent {
-    AddBlock(Block),
+    AddBlock(Box<Block>),
+
+    /// The canonical chain switched branch, `removed` and `added` are ordered
+    /// from the oldest block after the fork point.
+    Reorg {
+        removed: Vec<Block>,
+        added: Vec<Block>,
+    },
 }
 
 #[async_trait]
@@ -2,6 +2,7 @@ use sha2::{Digest, Sha256};
 
 pub trait HashFunc: Clone {
     fn hash(&self, value: String) -> [u8; 32];
+    fn hash_bytes(&self, value: &[u8]) -> [u8; 32];
 }
 
 #[derive(Clone, Default)]
@@ -20,6 +21,10 @@ impl HashFunc for DefaultHasher {
             .try_into()
             .expect("Try to convert to slice of 32 bytes");
     }
+
+    fn hash_bytes(&self, value: &[u8]) -> [u8; 32] {
+        Sha256::digest(value).into()
+    }
 }
 
 impl HashFunc for DoubleHasher {
@@ -27,4 +32,8 @@ impl HashFunc for DoubleHasher {
         let first = Sha256::digest(value);
         Sha256::digest(first).try_into().expect("Cannot Hash value")
     }
+
+    fn hash_bytes(&self, value: &[u8]) -> [u8; 32] {
+        Sha256::digest(Sha256::digest(value)).into()
+    }
 }
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kademlia::{signature::Signature, NODE_ID_LENGTH};
+
+use super::{Block, BlockChain, BlockHeader, Transaction, LEGACY_BLOCK_VERSION};
+
+type MerkleRoot = [u8; 32];
+type Hash = [u8; 32];
+
+/// Difficulty every legacy block was mined at, before retargeting existed.
+pub(crate) const LEGACY_DIFFICULTY: u32 = 5;
+
+/// Header layout persisted before the header carried its version and miner.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LegacyBlockHeader {
+    pub index: u64,
+    pub difficulty: u32,
+    pub timestamp: u128,
+    pub merkle_root: MerkleRoot,
+    pub nonce: u32,
+    pub prev_hash: Hash,
+    pub hash: Hash,
+    pub signature: Option<Signature>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LegacyBlock {
+    pub header: LegacyBlockHeader,
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LegacyBlockChain {
+    dificulty: u32,
+    blocks: Vec<LegacyBlock>,
+}
+
+impl From<LegacyBlockHeader> for BlockHeader {
+    fn from(header: LegacyBlockHeader) -> Self {
+        // legacy blocks were always signed by the node that mined them
+        let miner = header
+            .signature
+            .as_ref()
+            .map_or([0u8; NODE_ID_LENGTH], |signature| signature.pub_key);
+
+        BlockHeader {
+            version: LEGACY_BLOCK_VERSION,
+            index: header.index,
+            difficulty: header.difficulty,
+            timestamp: header.timestamp,
+            merkle_root: header.merkle_root,
+            nonce: header.nonce,
+            prev_hash: header.prev_hash,
+            hash: header.hash,
+            miner,
+            signature: header.signature,
+            state_root: [0u8; 32],
+        }
+    }
+}
+
+impl From<LegacyBlock> for Block {
+    fn from(block: LegacyBlock) -> Self {
+        Block {
+            header: block.header.into(),
+            transactions: block.transactions,
+        }
+    }
+}
+
+impl From<LegacyBlockChain> for BlockChain {
+    fn from(block_chain: LegacyBlockChain) -> Self {
+        BlockChain::from_blocks(block_chain.blocks.into_iter().map(Block::from).collect())
+    }
+}
@@ -2,14 +2,32 @@ mod block;
 mod block_builder;
 mod block_header;
 mod chain;
+mod consensus;
 mod event;
 mod hash_func;
+mod legacy;
+mod orphan_pool;
+mod params;
+mod pow;
+mod pow_workers;
+mod reputation;
+mod state;
 mod transaction;
 mod transaction_pool;
 
 pub use block::Block;
-pub use block_header::BlockHeader;
+pub use block_builder::{BlockBuilder, MiningError};
+pub use block_header::{BlockHeader, BLOCK_VERSION, LEGACY_BLOCK_VERSION};
 pub use chain::{BlockChain, BlockChainError};
+pub use consensus::{Consensus, ConsensusKind};
 pub use event::{BlockChainEvent, BlockChainEventHandler};
 pub use hash_func::{DefaultHasher, DoubleHasher, HashFunc};
-pub use transaction::{Transaction, TransactionData};
+pub use legacy::LegacyBlockChain;
+pub use orphan_pool::OrphanPool;
+pub use params::{ChainParams, Checkpoint, Validator};
+pub use pow::{retarget, validate_hash, ProofOfWork};
+pub use pow_workers::{default_threads, PowWorkers};
+pub use reputation::{MinerRegistration, ProofOfReputation};
+pub use state::{verify_entry, StateMachine, StateTransition, WorldState};
+pub use transaction::{Transaction, TransactionData, TransactionError};
+pub use transaction_pool::{PoolLimits, TransactionPoolError};
@@ -0,0 +1,143 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::Block;
+
+type Hash = [u8; 32];
+
+pub const MAX_ORPHANS: usize = 128;
+pub const MAX_ORPHAN_AGE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Debug)]
+struct Orphan {
+    block: Block,
+    received_at: Instant,
+}
+
+/// Blocks received before their parent, waiting to be connected to the tree.
+#[derive(Clone, Debug)]
+pub struct OrphanPool {
+    max_orphans: usize,
+    max_age: Duration,
+    orphans: HashMap<Hash, Orphan>,
+
+    /// Hashes of the pooled blocks, indexed by the parent they are waiting for.
+    children: HashMap<Hash, Vec<Hash>>,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(MAX_ORPHANS, MAX_ORPHAN_AGE)
+    }
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize, max_age: Duration) -> Self {
+        Self {
+            max_orphans,
+            max_age,
+            orphans: HashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    pub fn insert(&mut self, block: Block) {
+        let hash = block.header.hash;
+        if self.contains(&hash) {
+            return;
+        }
+
+        self.children
+            .entry(block.header.prev_hash)
+            .or_default()
+            .push(hash);
+
+        self.orphans.insert(
+            hash,
+            Orphan {
+                block,
+                received_at: Instant::now(),
+            },
+        );
+
+        self.prune();
+    }
+
+    /// Hash of the block missing below the orphan sub-chain `hash` belongs to.
+    pub fn missing_parent(&self, hash: &Hash) -> Option<Hash> {
+        let mut orphan = self.orphans.get(hash)?;
+
+        while let Some(parent) = self.orphans.get(&orphan.block.header.prev_hash) {
+            orphan = parent;
+        }
+
+        Some(orphan.block.header.prev_hash)
+    }
+
+    /// Removes and returns the orphans waiting for `parent`.
+    pub fn take_children(&mut self, parent: &Hash) -> Vec<Block> {
+        self.children
+            .remove(parent)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|hash| self.orphans.remove(&hash))
+            .map(|orphan| orphan.block)
+            .collect()
+    }
+
+    fn remove(&mut self, hash: &Hash) {
+        let Some(orphan) = self.orphans.remove(hash) else {
+            return;
+        };
+
+        let prev_hash = orphan.block.header.prev_hash;
+        if let Some(siblings) = self.children.get_mut(&prev_hash) {
+            siblings.retain(|sibling| sibling != hash);
+
+            if siblings.is_empty() {
+                self.children.remove(&prev_hash);
+            }
+        }
+    }
+
+    /// Drops expired orphans, then the oldest ones while over capacity.
+    fn prune(&mut self) {
+        let expired = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| orphan.received_at.elapsed() > self.max_age)
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+
+        for hash in expired {
+            self.remove(&hash);
+        }
+
+        while self.orphans.len() > self.max_orphans {
+            let Some(oldest) = self
+                .orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.received_at)
+                .map(|(hash, _)| *hash)
+            else {
+                break;
+            };
+
+            self.remove(&oldest);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::ConsensusKind;
+use crate::utils;
+
+/// Block the whole network agrees on, no branch may replace it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "HexCheckpoint", into = "HexCheckpoint")]
+pub struct Checkpoint {
+    pub height: u64,
+    pub hash: [u8; 32],
+}
+
+/// Configured layout of a [`Checkpoint`], with its hash hex encoded.
+#[derive(Serialize, Deserialize)]
+struct HexCheckpoint {
+    height: u64,
+    hash: String,
+}
+
+impl TryFrom<HexCheckpoint> for Checkpoint {
+    type Error = String;
+
+    fn try_from(checkpoint: HexCheckpoint) -> Result<Self, Self::Error> {
+        let hash = hex::decode(&checkpoint.hash)
+            .ok()
+            .and_then(utils::to_32bytes)
+            .ok_or_else(|| format!("Invalid checkpoint hash {}", checkpoint.hash))?;
+
+        Ok(Checkpoint {
+            height: checkpoint.height,
+            hash,
+        })
+    }
+}
+
+impl From<Checkpoint> for HexCheckpoint {
+    fn from(checkpoint: Checkpoint) -> Self {
+        HexCheckpoint {
+            height: checkpoint.height,
+            hash: hex::encode(checkpoint.hash),
+        }
+    }
+}
+
+/// Miner bonded from the genesis, configured by its hex encoded key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Validator(pub [u8; 32]);
+
+impl TryFrom<String> for Validator {
+    type Error = String;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        hex::decode(&key)
+            .ok()
+            .and_then(utils::to_32bytes)
+            .map(Validator)
+            .ok_or_else(|| format!("Invalid validator key {}", key))
+    }
+}
+
+impl From<Validator> for String {
+    fn from(validator: Validator) -> Self {
+        hex::encode(validator.0)
+    }
+}
+
+/// Consensus parameters every node of a network must agree on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainParams {
+    pub consensus: ConsensusKind,
+
+    /// Difficulty, in leading zero nibbles, of the first block after the genesis.
+    pub initial_difficulty: u32,
+    pub min_difficulty: u32,
+    pub max_difficulty: u32,
+
+    /// Expected time between two blocks, in seconds.
+    pub target_block_interval: u64,
+
+    /// Number of blocks between two difficulty adjustments.
+    pub retarget_window: u64,
+
+    /// Number of recent blocks a miner's reputation is counted over.
+    pub reputation_window: u64,
+
+    /// Reputation a miner needs to take part in the block lottery, at least 1.
+    pub reputation_threshold: u64,
+
+    /// Miners credited the threshold from the genesis, so the network has
+    /// someone to include the first registrations.
+    pub validators: Vec<Validator>,
+
+    /// Difficulty, in leading zero nibbles, of the proof of work a new miner
+    /// pays to register.
+    pub registration_difficulty: u32,
+
+    /// Duration of a block lottery slot, in seconds.
+    pub slot_duration: u64,
+
+    /// How far ahead of the local clock a block timestamp may be, in seconds.
+    pub max_future_drift: u64,
+
+    /// Confirmations after which a block can't be reorganised anymore, 0
+    /// disables the rolling finality.
+    pub finality_depth: u64,
+
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl ChainParams {
+    pub fn target_block_interval(&self) -> Duration {
+        Duration::from_secs(self.target_block_interval)
+    }
+
+    pub fn retarget_window(&self) -> u64 {
+        self.retarget_window.max(2)
+    }
+
+    pub fn reputation_threshold(&self) -> u64 {
+        self.reputation_threshold.max(1)
+    }
+
+    pub fn slot_duration(&self) -> Duration {
+        Duration::from_secs(self.slot_duration.max(1))
+    }
+
+    pub fn max_future_drift(&self) -> Duration {
+        Duration::from_secs(self.max_future_drift)
+    }
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            consensus: ConsensusKind::default(),
+            initial_difficulty: 5,
+            min_difficulty: 1,
+            max_difficulty: 64,
+            target_block_interval: 10,
+            retarget_window: 10,
+            reputation_window: 100,
+            reputation_threshold: 1,
+            validators: vec![],
+            registration_difficulty: 6,
+            slot_duration: 1,
+            max_future_drift: 60,
+            finality_depth: 100,
+            checkpoints: vec![],
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::AtomicBool;
+
+use super::{
+    block_builder::{BlockBuilder, MiningError},
+    consensus::Consensus,
+    legacy::LEGACY_DIFFICULTY,
+    pow_workers::PowWorkers,
+    Block, BlockChain, BlockChainError, BlockHeader, ChainParams, DoubleHasher,
+    LEGACY_BLOCK_VERSION,
+};
+
+/// Checks that `hash` starts with at least `difficulty` zero nibbles.
+pub fn validate_hash(hash: &[u8; 32], difficulty: u32) -> bool {
+    let nibbles = difficulty as usize;
+    let full_bytes = nibbles / 2;
+    let has_half_nibble = nibbles % 2 == 1;
+
+    if full_bytes > hash.len() || (has_half_nibble && full_bytes == hash.len()) {
+        return false;
+    }
+
+    if hash[..full_bytes].iter().any(|byte| *byte != 0) {
+        return false;
+    }
+
+    !has_half_nibble || (hash[full_bytes] >> 4) == 0
+}
+
+/// Expected number of hashes to mine a block, every nibble being a 16x increase.
+pub fn block_work(difficulty: u32) -> u128 {
+    1u128
+        .checked_shl(difficulty.saturating_mul(4))
+        .unwrap_or(u128::MAX)
+}
+
+/// Difficulty of the block following `parent`, where `first` is the start of
+/// the retarget window closing at `parent`.
+///
+/// Difficulty moves by a single nibble, a 16x change in work, so it only
+/// adjusts once the window took 4x longer or shorter than targeted.
+pub fn retarget(params: &ChainParams, first: &BlockHeader, parent: &BlockHeader) -> u32 {
+    let intervals = parent.index.saturating_sub(first.index).max(1);
+    let expected = params.target_block_interval().as_nanos() * intervals as u128;
+    let actual = parent.timestamp.saturating_sub(first.timestamp);
+
+    let difficulty = if actual < expected / 4 {
+        parent.difficulty.saturating_add(1)
+    } else if actual > expected.saturating_mul(4) {
+        parent.difficulty.saturating_sub(1)
+    } else {
+        parent.difficulty
+    };
+
+    difficulty.clamp(params.min_difficulty, params.max_difficulty)
+}
+
+/// Proof of work, the heaviest chain being the one with the most expected hashes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProofOfWork;
+
+impl Consensus for ProofOfWork {
+    /// Only derived from the header timestamps of the branch `parent` belongs to.
+    fn next_difficulty(&self, chain: &BlockChain, parent: &BlockHeader) -> u32 {
+        let params = chain.params();
+        if parent.index == 0 {
+            return params.initial_difficulty;
+        }
+
+        let window = params.retarget_window();
+        let height = parent.index + 1;
+
+        if !height.is_multiple_of(window) {
+            return parent.difficulty;
+        }
+
+        // the genesis timestamp is fixed, so the window never starts on it
+        let first_height = (height - window).max(1);
+        let Some(first) = chain.get_ancestor(parent.hash, first_height) else {
+            return parent.difficulty;
+        };
+
+        retarget(params, &first.header, parent)
+    }
+
+    fn verify_seal(&self, header: &BlockHeader) -> bool {
+        header.meets_difficulty()
+    }
+
+    fn validate_header(
+        &self,
+        chain: &BlockChain,
+        parent: &BlockHeader,
+        header: &BlockHeader,
+    ) -> Result<(), BlockChainError> {
+        let expected_difficulty = if header.version == LEGACY_BLOCK_VERSION {
+            LEGACY_DIFFICULTY
+        } else {
+            self.next_difficulty(chain, parent)
+        };
+
+        if header.difficulty != expected_difficulty || !self.verify_seal(header) {
+            return Err(BlockChainError::InvalidProofOfWork);
+        }
+
+        Ok(())
+    }
+
+    fn block_weight(&self, header: &BlockHeader) -> u128 {
+        block_work(header.difficulty)
+    }
+
+    fn seal(
+        &self,
+        builder: &BlockBuilder,
+        workers: &PowWorkers,
+        cancelled: &AtomicBool,
+    ) -> Result<Block, MiningError> {
+        builder.mine(DoubleHasher {}, workers, cancelled)
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::Instant,
+};
+
+type Hash = [u8; 32];
+
+/// Hashes a worker computes between two updates of the shared counters.
+const REPORT_INTERVAL: u64 = 4096;
+
+#[derive(Debug)]
+struct HashRate {
+    epoch: Instant,
+    hashes: AtomicU64,
+
+    /// Nanoseconds spent on finished searches.
+    busy_nanos: AtomicU64,
+
+    /// Start of the running search in nanoseconds since `epoch`, 0 when idle.
+    searching_since: AtomicU64,
+}
+
+impl HashRate {
+    fn nanos_since_epoch(&self) -> u64 {
+        u64::try_from(self.epoch.elapsed().as_nanos()).unwrap_or(u64::MAX)
+    }
+}
+
+impl Default for HashRate {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            hashes: AtomicU64::new(0),
+            busy_nanos: AtomicU64::new(0),
+            searching_since: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Proof of work search splitting the nonce space across worker threads.
+#[derive(Clone, Debug)]
+pub struct PowWorkers {
+    threads: usize,
+    stats: Arc<HashRate>,
+}
+
+impl Default for PowWorkers {
+    fn default() -> Self {
+        Self::new(default_threads())
+    }
+}
+
+/// One worker per available core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+impl PowWorkers {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            stats: Arc::default(),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Hashes computed since the workers were created.
+    pub fn hashes(&self) -> u64 {
+        self.stats.hashes.load(Ordering::Relaxed)
+    }
+
+    /// Average hashes per second while searching.
+    pub fn hash_rate(&self) -> f64 {
+        let mut busy = self.stats.busy_nanos.load(Ordering::Relaxed);
+
+        let since = self.stats.searching_since.load(Ordering::Relaxed);
+        if since != 0 {
+            busy = busy.saturating_add(self.stats.nanos_since_epoch().saturating_sub(since));
+        }
+
+        if busy == 0 {
+            return 0.0;
+        }
+
+        self.hashes() as f64 * 1e9 / busy as f64
+    }
+
+    /// Tries every `u32` nonce until `attempt` hashes one meeting the target,
+    /// returning it along with its hash.
+    ///
+    /// Returns `None` once the whole nonce space was tried or `cancelled` is set.
+    pub fn search<F>(&self, cancelled: &AtomicBool, attempt: F) -> Option<(u32, Hash)>
+    where
+        F: Fn(u32) -> Option<Hash> + Sync,
+    {
+        let stats = &self.stats;
+        let started = Instant::now();
+        stats
+            .searching_since
+            .store(stats.nanos_since_epoch().max(1), Ordering::Relaxed);
+
+        let found = OnceLock::new();
+        let space = u64::from(u32::MAX) + 1;
+        let threads = self.threads as u64;
+
+        thread::scope(|scope| {
+            for worker in 0..threads {
+                let (found, attempt) = (&found, &attempt);
+                let nonces = space * worker / threads..space * (worker + 1) / threads;
+
+                scope.spawn(move || {
+                    let mut hashes = 0;
+
+                    for nonce in nonces {
+                        if cancelled.load(Ordering::Relaxed) || found.get().is_some() {
+                            break;
+                        }
+
+                        hashes += 1;
+                        if hashes == REPORT_INTERVAL {
+                            stats.hashes.fetch_add(hashes, Ordering::Relaxed);
+                            hashes = 0;
+                        }
+
+                        if let Some(hash) = attempt(nonce as u32) {
+                            let _ = found.set((nonce as u32, hash));
+                            break;
+                        }
+                    }
+
+                    stats.hashes.fetch_add(hashes, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let elapsed = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        stats.busy_nanos.fetch_add(elapsed, Ordering::Relaxed);
+        stats.searching_since.store(0, Ordering::Relaxed);
+
+        found.into_inner()
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    sync::atomic::AtomicBool,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    block_builder::{BlockBuilder, MiningError},
+    block_header::current_timestamp,
+    consensus::Consensus,
+    pow::validate_hash,
+    pow_workers::PowWorkers,
+    Block, BlockChain, BlockChainError, BlockHeader, DoubleHasher, HashFunc, TransactionData,
+};
+
+type Hash = [u8; 32];
+type PublicKey = [u8; 32];
+
+/// Slots searched past the current one for the next slot a miner wins.
+pub const MAX_SLOT_LOOKAHEAD: u32 = 4096;
+
+/// Registers its sender as a miner, paid with a proof of work over its key so
+/// new identities aren't free.
+///
+/// The sender is bonded, i.e. credited the reputation threshold, as long as the
+/// block including it is in the reputation window.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MinerRegistration {
+    pub nonce: u32,
+}
+
+impl MinerRegistration {
+    /// Searches the registration of `miner` on `workers`, until `cancelled` is set.
+    pub fn solve(
+        miner: &PublicKey,
+        difficulty: u32,
+        workers: &PowWorkers,
+        cancelled: &AtomicBool,
+    ) -> Option<Self> {
+        let (nonce, _) = workers.search(cancelled, |nonce| {
+            let hash = Self::proof(miner, nonce);
+            validate_hash(&hash, difficulty).then_some(hash)
+        })?;
+
+        Some(Self { nonce })
+    }
+
+    pub fn is_valid(&self, miner: &PublicKey, difficulty: u32) -> bool {
+        validate_hash(&Self::proof(miner, self.nonce), difficulty)
+    }
+
+    fn proof(miner: &PublicKey, nonce: u32) -> Hash {
+        let mut input = Vec::with_capacity(32 + 4);
+        input.extend_from_slice(miner);
+        input.extend_from_slice(&nonce.to_le_bytes());
+
+        DoubleHasher.hash_bytes(&input)
+    }
+}
+
+#[typetag::serde]
+impl TransactionData for MinerRegistration {
+    fn get_hash(&self) -> Option<PublicKey> {
+        let encoded = bincode::serde::encode_to_vec(self, bincode::config::standard()).ok()?;
+
+        Some(DoubleHasher.hash(hex::encode(encoded)))
+    }
+
+    fn clone_dyn(&self) -> Box<dyn TransactionData> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Blocks signed by every miner over the reputation window of a branch, plus
+/// the bond of the registered ones. Invalid blocks never make it to the branch,
+/// so they earn their miner nothing.
+#[derive(Debug, Default)]
+struct Reputation {
+    scores: HashMap<PublicKey, u64>,
+    total: u64,
+}
+
+impl Reputation {
+    /// Reputation over the last blocks of the branch ending at `parent`.
+    fn of_branch(chain: &BlockChain, parent: &BlockHeader) -> Self {
+        let params = chain.params();
+        let mut reputation = Self::default();
+        let mut bonded = params
+            .validators
+            .iter()
+            .map(|validator| validator.0)
+            .collect::<HashSet<_>>();
+
+        let mut hash = parent.hash;
+        for _ in 0..params.reputation_window {
+            // the genesis has no miner
+            let Some(block) = chain.get_block_by_hash(hash).filter(|b| b.header.index > 0) else {
+                break;
+            };
+
+            *reputation.scores.entry(block.header.miner).or_default() += 1;
+
+            bonded.extend(
+                block
+                    .get_transaction::<MinerRegistration>()
+                    .filter(|(transaction, registration)| {
+                        registration.is_valid(&transaction.from, params.registration_difficulty)
+                    })
+                    .map(|(transaction, _)| transaction.from),
+            );
+
+            hash = block.header.prev_hash;
+        }
+
+        for miner in bonded {
+            *reputation.scores.entry(miner).or_default() += params.reputation_threshold();
+        }
+
+        reputation.total = reputation.scores.values().sum();
+        reputation
+    }
+
+    fn score(&self, miner: &PublicKey) -> u64 {
+        self.scores.get(miner).copied().unwrap_or_default()
+    }
+
+    fn is_eligible(&self, miner: &PublicKey, threshold: u64) -> bool {
+        self.score(miner) >= threshold
+    }
+
+    /// Whether `miner` wins `slot` after `parent`, with odds proportional to its
+    /// share of the reputation.
+    fn wins(&self, parent: &BlockHeader, miner: &PublicKey, slot: u32) -> bool {
+        let mut input = Vec::with_capacity(32 + 4 + 32);
+        input.extend_from_slice(&parent.hash);
+        input.extend_from_slice(&slot.to_le_bytes());
+        input.extend_from_slice(miner);
+
+        let ticket = DoubleHasher.hash_bytes(&input);
+        let ticket = u64::from_le_bytes(ticket[..8].try_into().expect("Ticket is 32 bytes long"));
+
+        ticket % self.total.max(1) < self.score(miner)
+    }
+}
+
+/// Proof of reputation, where miners take turns by a lottery weighted by the
+/// blocks they signed recently instead of searching a nonce.
+///
+/// Time after the parent is split in slots, the header nonce holds the slot
+/// the block was signed for and its timestamp the start of that slot.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProofOfReputation;
+
+impl ProofOfReputation {
+    /// Whether `miner` may sign the block following the canonical tip, as
+    /// opposed to having to register first.
+    pub fn is_eligible(chain: &BlockChain, miner: &PublicKey) -> bool {
+        chain.get_blockchain_head().is_some_and(|head| {
+            Reputation::of_branch(chain, &head.header)
+                .is_eligible(miner, chain.params().reputation_threshold())
+        })
+    }
+
+    fn slot_timestamp(chain: &BlockChain, parent: &BlockHeader, slot: u32) -> u128 {
+        let slot_duration = chain.params().slot_duration().as_nanos();
+        parent.timestamp + u128::from(slot) * slot_duration
+    }
+}
+
+impl Consensus for ProofOfReputation {
+    fn next_difficulty(&self, _chain: &BlockChain, _parent: &BlockHeader) -> u32 {
+        0
+    }
+
+    fn verify_seal(&self, header: &BlockHeader) -> bool {
+        header.difficulty == 0 && header.nonce > 0
+    }
+
+    fn validate_header(
+        &self,
+        chain: &BlockChain,
+        parent: &BlockHeader,
+        header: &BlockHeader,
+    ) -> Result<(), BlockChainError> {
+        if !self.verify_seal(header)
+            || header.timestamp != Self::slot_timestamp(chain, parent, header.nonce)
+        {
+            return Err(BlockChainError::InvalidBlock);
+        }
+
+        let reputation = Reputation::of_branch(chain, parent);
+        if !reputation.is_eligible(&header.miner, chain.params().reputation_threshold())
+            || !reputation.wins(parent, &header.miner, header.nonce)
+        {
+            return Err(BlockChainError::IneligibleMiner);
+        }
+
+        Ok(())
+    }
+
+    fn block_weight(&self, _header: &BlockHeader) -> u128 {
+        1
+    }
+
+    /// Schedules the block on the next slot the miner wins, leaving it
+    /// unscheduled when there is none in sight.
+    fn prepare(&self, chain: &BlockChain, builder: &mut BlockBuilder) {
+        let Some(miner) = builder.miner() else {
+            return;
+        };
+
+        let Some(parent) = chain.get_block_by_hash(builder.prev_hash()) else {
+            return;
+        };
+
+        let parent = &parent.header;
+        let reputation = Reputation::of_branch(chain, parent);
+        if !reputation.is_eligible(&miner, chain.params().reputation_threshold()) {
+            return;
+        }
+
+        let elapsed = current_timestamp().saturating_sub(parent.timestamp);
+        let current = elapsed / chain.params().slot_duration().as_nanos();
+        let current = u32::try_from(current).unwrap_or(u32::MAX).max(1);
+
+        let Some(slot) = (current..=current.saturating_add(MAX_SLOT_LOOKAHEAD))
+            .find(|slot| reputation.wins(parent, &miner, *slot))
+        else {
+            return;
+        };
+
+        builder.schedule(Self::slot_timestamp(chain, parent, slot), slot);
+    }
+
+    fn seal(
+        &self,
+        builder: &BlockBuilder,
+        _workers: &PowWorkers,
+        cancelled: &AtomicBool,
+    ) -> Result<Block, MiningError> {
+        builder.seal_scheduled(DoubleHasher {}, cancelled)
+    }
+}
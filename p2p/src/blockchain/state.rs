@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::merkle::{verify_sparse_proof, SparseMerkleProof, SparseMerkleTree};
+
+use super::{Block, BlockHeader, Transaction, TransactionError, LEGACY_BLOCK_VERSION};
+
+type Hash = [u8; 32];
+type PublicKey = [u8; 32];
+
+/// Key-value state every transaction type reads and writes, the account
+/// nonces included. Cloning it is cheap, the tree nodes are shared.
+#[derive(Clone, Debug, Default)]
+pub struct WorldState {
+    entries: SparseMerkleTree,
+
+    /// Height and timestamp of the last block applied, so rules can depend on
+    /// the chain's progress. Not part of the root, the header commits to both.
+    height: u64,
+    timestamp: u128,
+}
+
+impl WorldState {
+    /// Keys of the account nonces.
+    const NONCE_PREFIX: &'static [u8] = b"nonce/";
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key)
+    }
+
+    /// Decodes the value at `key`, see [`StateTransition::put_as`].
+    pub fn get_as<TValue: DeserializeOwned>(&self, key: &[u8]) -> Option<TValue> {
+        decode(self.get(key)?)
+    }
+
+    /// Entries whose key starts with `prefix`, in key order.
+    pub fn scan_prefix<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + 'a {
+        let mut entries = self
+            .entries
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+
+        entries.into_iter()
+    }
+
+    /// Decoded values whose key starts with `prefix`, skipping the undecodable ones.
+    pub fn scan_prefix_as<'a, TValue: DeserializeOwned>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = TValue> + 'a {
+        self.scan_prefix(prefix)
+            .filter_map(|(_, value)| decode(value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Nonce the next transaction of `account` must carry.
+    pub fn next_nonce(&self, account: &PublicKey) -> u32 {
+        self.get(&Self::nonce_key(account))
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_le_bytes)
+            .unwrap_or_default()
+    }
+
+    fn nonce_key(account: &PublicKey) -> Vec<u8> {
+        [Self::NONCE_PREFIX, account].concat()
+    }
+
+    /// Root of the sparse Merkle tree over every entry, committed by the
+    /// block headers.
+    pub fn root(&self) -> Hash {
+        self.entries.root()
+    }
+
+    /// Root the state would have once `block` is applied, leaving it untouched.
+    pub fn root_after(&self, block: &Block) -> Result<Hash, (Hash, TransactionError)> {
+        let mut state = self.clone();
+        apply_transactions(&mut state, block)?;
+
+        Ok(state.root())
+    }
+
+    /// Applies `transaction` as part of the block of `header`, for simulating a
+    /// block before it's sealed. The state is left untouched when it's rejected.
+    pub(crate) fn apply_transaction(
+        &mut self,
+        header: &BlockHeader,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        let mut undo = UndoLog {
+            tip: (self.height, self.timestamp),
+            ..UndoLog::default()
+        };
+
+        apply_transaction(self, header, transaction, &mut undo)
+            .inspect_err(|_| rollback(self, undo))
+    }
+
+    /// Proof that the entry at `key` is part of the state, see [`verify_entry`].
+    /// There is no proof that a key is absent.
+    pub fn proof(&self, key: &[u8]) -> Option<SparseMerkleProof> {
+        self.entries.proof(key)
+    }
+}
+
+/// Checks the entry `key` = `value` against the state root of a header.
+pub fn verify_entry(key: &[u8], value: &[u8], proof: &SparseMerkleProof, state_root: Hash) -> bool {
+    verify_sparse_proof(key, value, proof, state_root)
+}
+
+fn decode<TValue: DeserializeOwned>(bytes: &[u8]) -> Option<TValue> {
+    bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+        .ok()
+        .map(|(value, _)| value)
+}
+
+/// Previous value of every key a block wrote, in write order.
+#[derive(Clone, Debug, Default)]
+struct UndoLog {
+    entries: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+
+    /// Height and timestamp of the state before the block.
+    tip: (u64, u128),
+}
+
+/// Writes of a single transaction, recorded so its block can be reverted.
+pub struct StateTransition<'a> {
+    state: &'a mut WorldState,
+    undo: &'a mut UndoLog,
+}
+
+impl StateTransition<'_> {
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.state.get(key)
+    }
+
+    pub fn get_as<TValue: DeserializeOwned>(&self, key: &[u8]) -> Option<TValue> {
+        self.state.get_as(key)
+    }
+
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        let previous = self.state.entries.insert(key.clone(), value);
+        self.undo.entries.push((key, previous));
+    }
+
+    /// Stores `value` bincode encoded, failing only if it can't be serialized.
+    pub fn put_as<TValue: Serialize>(&mut self, key: Vec<u8>, value: &TValue) -> Option<()> {
+        let value = bincode::serde::encode_to_vec(value, bincode::config::standard()).ok()?;
+        self.put(key, value);
+        Some(())
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        if let Some(previous) = self.state.entries.remove(key) {
+            self.undo.entries.push((key.to_vec(), Some(previous)));
+        }
+    }
+}
+
+/// [`WorldState`] as of a given block, maintained by applying the transactions
+/// of every block appended and reverting them when a reorg takes it away.
+#[derive(Clone, Debug, Default)]
+pub struct StateMachine {
+    state: WorldState,
+
+    /// Undo log and height of the applied blocks that may still be reverted.
+    undo: HashMap<Hash, (u64, UndoLog)>,
+}
+
+impl StateMachine {
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    /// Applies the transactions of `block` in order, leaving the state untouched
+    /// when one of them is rejected.
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), (Hash, TransactionError)> {
+        let undo = apply_transactions(&mut self.state, block)?;
+
+        self.undo
+            .insert(block.header.hash, (block.header.index, undo));
+
+        Ok(())
+    }
+
+    /// Reverts `block`, the last block applied, returning false when its undo
+    /// log was already pruned.
+    pub fn revert_block(&mut self, block: &Block) -> bool {
+        let Some((_, undo)) = self.undo.remove(&block.header.hash) else {
+            return false;
+        };
+
+        rollback(&mut self.state, undo);
+        true
+    }
+
+    /// Drops the undo logs of the blocks at or below `height`, which can't be
+    /// reverted anymore.
+    pub fn prune(&mut self, height: u64) {
+        self.undo.retain(|_, (applied_at, _)| *applied_at > height);
+    }
+}
+
+/// Applies the transactions of `block` to `state`, rolling back the ones
+/// already applied when one is rejected.
+fn apply_transactions(
+    state: &mut WorldState,
+    block: &Block,
+) -> Result<UndoLog, (Hash, TransactionError)> {
+    let mut undo = UndoLog {
+        tip: (state.height, state.timestamp),
+        ..UndoLog::default()
+    };
+
+    for transaction in &block.transactions {
+        if let Err(e) = apply_transaction(state, &block.header, transaction, &mut undo) {
+            rollback(state, undo);
+            return Err((transaction.id(), e));
+        }
+    }
+
+    state.height = block.header.index;
+    state.timestamp = block.header.timestamp;
+
+    Ok(undo)
+}
+
+fn apply_transaction(
+    state: &mut WorldState,
+    header: &BlockHeader,
+    transaction: &Transaction,
+    undo: &mut UndoLog,
+) -> Result<(), TransactionError> {
+    // legacy blocks were applied as is, whatever they carried
+    let legacy = header.version == LEGACY_BLOCK_VERSION;
+
+    if !legacy {
+        let expected = state.next_nonce(&transaction.from);
+
+        if transaction.nonce != expected {
+            return Err(TransactionError::InvalidNonce {
+                expected,
+                found: transaction.nonce,
+            });
+        }
+
+        transaction.data.validate(header, transaction, state)?;
+    }
+
+    let mut transition = StateTransition { state, undo };
+
+    if !legacy {
+        let next = transaction
+            .nonce
+            .checked_add(1)
+            .ok_or(TransactionError::NonceOverflow)?;
+
+        transition.put(
+            WorldState::nonce_key(&transaction.from),
+            next.to_le_bytes().to_vec(),
+        );
+    }
+
+    transaction.data.apply(header, transaction, &mut transition)
+}
+
+fn rollback(state: &mut WorldState, undo: UndoLog) {
+    (state.height, state.timestamp) = undo.tip;
+
+    for (key, previous) in undo.entries.into_iter().rev() {
+        match previous {
+            Some(value) => state.entries.insert(key, value),
+            None => state.entries.remove(&key),
+        };
+    }
+}
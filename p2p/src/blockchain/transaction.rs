@@ -1,24 +1,65 @@
-use std::{any::Any, fmt::Debug};
+use std::{any::Any, error::Error as StdError, fmt::Debug};
 
 use chrono::Utc;
 use ed25519_dalek::PUBLIC_KEY_LENGTH;
-use rand::{rng, RngCore};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::kademlia::{
     secret_key::SecretPair,
     signature::{HandleSignature, Signature},
 };
 
-use super::{DoubleHasher, HashFunc};
+use super::{BlockHeader, DoubleHasher, HashFunc, StateTransition, WorldState};
 
 type PublicKey = [u8; PUBLIC_KEY_LENGTH];
 
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("Transaction data has no fingerprint")]
+    MissingFingerPrint,
+
+    #[error("Transaction signature doesn't match its sender")]
+    InvalidSignature,
+
+    #[error("Expected nonce {expected}, found {found}")]
+    InvalidNonce { expected: u32, found: u32 },
+
+    #[error("Account nonce overflowed")]
+    NonceOverflow,
+
+    #[error("Transaction rejected: {0}")]
+    Rejected(Box<dyn StdError + Send + Sync>),
+}
+
 #[typetag::serde]
 pub trait TransactionData: erased_serde::Serialize + Debug + Send + Sync + 'static {
     fn get_hash(&self) -> Option<PublicKey>;
     fn clone_dyn(&self) -> Box<dyn TransactionData>;
     fn as_any(&self) -> &dyn Any;
+
+    /// Checks `transaction`, carrying this data, against the world state it
+    /// would be applied to as part of the block of `header`. Rejected
+    /// transactions are neither admitted to the pool nor allowed in a block.
+    fn validate(
+        &self,
+        _header: &BlockHeader,
+        _transaction: &Transaction,
+        _state: &WorldState,
+    ) -> Result<(), TransactionError> {
+        Ok(())
+    }
+
+    /// Applies `transaction`, carrying this data, to the world state once the
+    /// block of `header` includes it.
+    fn apply(
+        &self,
+        _header: &BlockHeader,
+        _transaction: &Transaction,
+        _state: &mut StateTransition,
+    ) -> Result<(), TransactionError> {
+        Ok(())
+    }
 }
 
 impl Clone for Box<dyn TransactionData> {
@@ -49,20 +90,17 @@ impl Debug for Transaction {
 }
 
 impl Transaction {
-    pub fn new<TData: TransactionData>(pair: SecretPair, data: TData) -> Option<Transaction> {
-        let hash_func = DoubleHasher::default();
-
+    /// Signs `data` as the `nonce`-th transaction of the pair's account, see
+    /// [`BlockChain::next_nonce`](super::BlockChain::next_nonce).
+    pub fn new<TData: TransactionData>(
+        pair: SecretPair,
+        nonce: u32,
+        data: TData,
+    ) -> Option<Transaction> {
         let from = pair.public_key;
         let timestamp = Utc::now().timestamp();
-        let nonce: u32 = rng().next_u32();
-
-        let Some(data_finger_print) = data.get_hash() else {
-            return None;
-        };
-
-        let input = format!("{}{}{}", hex::encode(data_finger_print), timestamp, nonce);
-        let finger_print = hash_func.hash(input);
 
+        let finger_print = Self::finger_print(&data, timestamp, nonce)?;
         let signature = Signature::sign(pair, finger_print);
 
         Some(Transaction {
@@ -74,6 +112,84 @@ impl Transaction {
         })
     }
 
+    /// Digest signed by the sender, binding the data to its timestamp and nonce
+    /// in the fixed width layout of [`Transaction::encode`].
+    fn finger_print(data: &dyn TransactionData, timestamp: i64, nonce: u32) -> Option<[u8; 32]> {
+        let data_finger_print = data.get_hash()?;
+
+        let mut input = Vec::with_capacity(32 + 8 + 4);
+        input.extend_from_slice(&data_finger_print);
+        input.extend_from_slice(&timestamp.to_le_bytes());
+        input.extend_from_slice(&nonce.to_le_bytes());
+
+        Some(DoubleHasher::default().hash_bytes(&input))
+    }
+
+    /// Digest signed by the transactions of legacy blocks. Timestamp and nonce
+    /// run together in it, so different pairs may share a signature.
+    fn legacy_finger_print(
+        data: &dyn TransactionData,
+        timestamp: i64,
+        nonce: u32,
+    ) -> Option<[u8; 32]> {
+        let data_finger_print = data.get_hash()?;
+
+        let input = format!("{}{}{}", hex::encode(data_finger_print), timestamp, nonce);
+        Some(DoubleHasher::default().hash(input))
+    }
+
+    /// Checks the signature was made by `from` over this transaction's fingerprint.
+    pub fn verify(&self) -> Result<(), TransactionError> {
+        self.verify_finger_print(Self::finger_print)
+    }
+
+    /// Checks the signature of a transaction carried by a legacy block.
+    pub fn verify_legacy(&self) -> Result<(), TransactionError> {
+        self.verify_finger_print(Self::legacy_finger_print)
+    }
+
+    fn verify_finger_print(
+        &self,
+        finger_print: fn(&dyn TransactionData, i64, u32) -> Option<[u8; 32]>,
+    ) -> Result<(), TransactionError> {
+        let finger_print = finger_print(self.data.as_ref(), self.timestamp, self.nonce)
+            .ok_or(TransactionError::MissingFingerPrint)?;
+
+        if !self.signature.validate_signature(self.from, finger_print) {
+            return Err(TransactionError::InvalidSignature);
+        }
+
+        Ok(())
+    }
+
+    /// Canonical little-endian encoding, independent of how the transaction
+    /// or its data are serialized.
+    pub fn encode(&self) -> Vec<u8> {
+        let data_finger_print = self.data.get_hash().unwrap_or_default();
+        let signature = &self.signature.signature;
+
+        let mut bytes = Vec::with_capacity(32 + 32 + 8 + 4 + 32 + 4 + signature.len());
+
+        bytes.extend_from_slice(&self.from);
+        bytes.extend_from_slice(&data_finger_print);
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes.extend_from_slice(&self.signature.pub_key);
+        bytes.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(signature);
+
+        bytes
+    }
+
+    /// Unique identifier of the transaction, the signature already commits to
+    /// the data, timestamp and nonce of its sender.
+    pub fn id(&self) -> [u8; 32] {
+        let mut input = self.from.to_vec();
+        input.extend_from_slice(&self.signature.signature);
+
+        DoubleHasher::default().hash_bytes(&input)
+    }
+
     pub fn get_data<TData: 'static>(&self) -> Option<&TData> {
         self.data.as_any().downcast_ref::<TData>()
     }
@@ -82,3 +198,49 @@ impl Transaction {
         serde_json::to_string(self).expect("Failed to serialize to JSON")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::MinerRegistration;
+
+    #[test]
+    fn finger_print_keeps_timestamp_and_nonce_apart() {
+        let data = MinerRegistration { nonce: 0 };
+
+        // both end in ...17000000053 once run together
+        assert_eq!(
+            Transaction::legacy_finger_print(&data, 1_700_000_005, 3),
+            Transaction::legacy_finger_print(&data, 170_000_000, 53)
+        );
+
+        assert_ne!(
+            Transaction::finger_print(&data, 1_700_000_005, 3),
+            Transaction::finger_print(&data, 170_000_000, 53)
+        );
+    }
+
+    #[test]
+    fn signature_doesnt_carry_over_to_another_nonce() {
+        let pair = SecretPair::generate_keys().expect("Failed to generate keys");
+        let data = MinerRegistration { nonce: 0 };
+        let finger_print =
+            Transaction::finger_print(&data, 1_700_000_005, 3).expect("Data has a fingerprint");
+
+        let mut transaction = Transaction {
+            from: pair.public_key,
+            data: Box::new(data),
+            signature: Signature::sign(pair, finger_print),
+            nonce: 3,
+            timestamp: 1_700_000_005,
+        };
+        assert!(transaction.verify().is_ok());
+
+        transaction.timestamp = 170_000_000;
+        transaction.nonce = 53;
+        assert!(matches!(
+            transaction.verify(),
+            Err(TransactionError::InvalidSignature)
+        ));
+    }
+}
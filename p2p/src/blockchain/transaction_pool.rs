@@ -1,55 +1,612 @@
-use log::error;
+use log::{error, info};
 use std::{
-    collections::VecDeque,
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
     sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 
-use super::{block, Transaction};
+use thiserror::Error;
 
+use super::{block, transaction::TransactionError, BlockHeader, Transaction, WorldState};
+
+type Id = [u8; 32];
+type PublicKey = [u8; 32];
+
+pub const MAX_POOL_TRANSACTIONS: usize = 4096;
+pub const MAX_POOL_BYTES: usize = 4 * 1024 * 1024;
+pub const MAX_SENDER_TRANSACTIONS: usize = 64;
+pub const MAX_HELD_TRANSACTIONS: usize = 256;
+pub const TRANSACTION_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Error)]
+pub enum TransactionPoolError {
+    #[error("Failed to lock transaction pool")]
+    Unavailable,
+
+    #[error("Transaction {id} is invalid: {1}", id = hex::encode(.0))]
+    InvalidTransaction([u8; 32], TransactionError),
+
+    #[error("Transaction {id} is already pending", id = hex::encode(.0))]
+    Duplicate([u8; 32]),
+
+    #[error("Transaction {id} is held until the transactions before it arrive", id = hex::encode(.0))]
+    Held([u8; 32]),
+
+    #[error("Sender has too many pending transactions")]
+    SenderLimit,
+
+    #[error("Transaction pool is full")]
+    PoolFull,
+}
+
+#[derive(Clone, Debug)]
+pub struct PoolLimits {
+    pub max_transactions: usize,
+    pub max_bytes: usize,
+    pub max_per_sender: usize,
+
+    /// Transactions held ahead of their sender's sequence, see [`TransactionPool::add_transaction`].
+    pub max_held: usize,
+    pub expiry: Duration,
+}
+
+impl Default for PoolLimits {
+    fn default() -> Self {
+        Self {
+            max_transactions: MAX_POOL_TRANSACTIONS,
+            max_bytes: MAX_POOL_BYTES,
+            max_per_sender: MAX_SENDER_TRANSACTIONS,
+            max_held: MAX_HELD_TRANSACTIONS,
+            expiry: TRANSACTION_EXPIRY,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct PendingTransaction {
+    transaction: Transaction,
+    size: usize,
+    received_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Mempool {
+    limits: PoolLimits,
+    transactions: HashMap<Id, PendingTransaction>,
+
+    /// Pending transaction ids of every sender, ordered by nonce.
+    senders: HashMap<PublicKey, BTreeMap<u32, Id>>,
+    bytes: usize,
+
+    /// Transactions ahead of their sender's next nonce, by sender and nonce,
+    /// admitted once the ones before them arrive.
+    held: HashMap<PublicKey, BTreeMap<u32, PendingTransaction>>,
+
+    /// Chain state with every pending transaction applied in mining order, so
+    /// new ones may depend on them. Dropped once a transaction leaves the pool
+    /// and rebuilt on the next admission.
+    pending_state: Option<WorldState>,
+}
+
+/// Pending transactions, mined oldest first without breaking any sender's
+/// nonce sequence.
 #[derive(Clone, Debug, Default)]
 pub struct TransactionPool {
-    transaction_pool: Arc<Mutex<VecDeque<Transaction>>>,
+    transaction_pool: Arc<Mutex<Mempool>>,
 }
 
 impl TransactionPool {
     pub fn new() -> Self {
+        Self::with_limits(PoolLimits::default())
+    }
+
+    pub fn with_limits(limits: PoolLimits) -> Self {
         Self {
-            transaction_pool: Arc::new(Mutex::new(VecDeque::new())),
+            transaction_pool: Arc::new(Mutex::new(Mempool {
+                limits,
+                ..Mempool::default()
+            })),
         }
     }
 
-    fn get_lock_pool(&self) -> Result<MutexGuard<VecDeque<Transaction>>, ()> {
+    fn get_lock_pool(&self) -> Result<MutexGuard<Mempool>, ()> {
         self.transaction_pool
             .lock()
             .map_err(|e| error!("Failed to lock transaction pool {}", e))
     }
 
-    pub fn add_transaction(&self, transaction: Transaction) -> Result<(), ()> {
+    /// Admits `transaction` if it's the next one in its sender's sequence, given
+    /// the nonces of the chain `state` and the transactions already pending.
+    ///
+    /// Its data is validated against the chain `state` with the pending
+    /// transactions applied, as part of the block of `header`, so it may depend
+    /// on any of them, e.g. a bid on an auction created by a pending one.
+    ///
+    /// A transaction a few nonces ahead, e.g. gossiped out of order, is held
+    /// until the ones before it are admitted.
+    pub fn add_transaction(
+        &self,
+        transaction: Transaction,
+        state: &WorldState,
+        header: &BlockHeader,
+    ) -> Result<(), TransactionPoolError> {
+        let id = transaction.id();
+        let invalid = |e| TransactionPoolError::InvalidTransaction(id, e);
+
+        transaction.verify().map_err(invalid)?;
+
+        let mut pool = self
+            .get_lock_pool()
+            .map_err(|_| TransactionPoolError::Unavailable)?;
+
+        pool.remove_expired();
+
+        if pool.transactions.contains_key(&id) || pool.is_held(&transaction) {
+            return Err(TransactionPoolError::Duplicate(id));
+        }
+
+        let from = transaction.from;
+        let expected = pool
+            .next_nonce(&from)
+            .unwrap_or_else(|| state.next_nonce(&from));
+
+        if transaction.nonce > expected
+            && transaction.nonce - expected < pool.limits.max_per_sender as u32
+        {
+            return pool.hold(transaction);
+        }
+
+        pool.admit(transaction, state, header)?;
+        pool.promote_held(&from, state, header);
+
+        Ok(())
+    }
+
+    /// Nonce following the last pending transaction of `account`, if any.
+    pub fn next_nonce(&self, account: &PublicKey) -> Option<u32> {
+        self.get_lock_pool().ok()?.next_nonce(account)
+    }
+
+    /// Puts back transactions that left the chain, they precede the pending ones
+    /// in their senders' sequences so they are mined first.
+    pub fn restore_transactions(&self, transactions: Vec<Transaction>) -> Result<(), ()> {
         let mut pool = self.get_lock_pool()?;
-        pool.push_back(transaction);
 
+        // backdated so they keep their priority over what is already pending
+        let received_at = pool
+            .transactions
+            .values()
+            .map(|pending| pending.received_at)
+            .min()
+            .unwrap_or_else(Instant::now);
+
+        for transaction in transactions {
+            if !pool.transactions.contains_key(&transaction.id()) {
+                pool.insert(transaction, received_at);
+            }
+        }
+
+        // they come first in mining order, so the pending ones apply after them
+        pool.pending_state = None;
+
+        pool.evict_overflow();
         Ok(())
     }
 
+    pub fn remove_transactions(&self, ids: &HashSet<[u8; 32]>) -> Result<(), ()> {
+        let mut pool = self.get_lock_pool()?;
+
+        for id in ids {
+            pool.remove(id);
+        }
+
+        Ok(())
+    }
+
+    /// Drops `id` along with the transactions following it in its sender's
+    /// sequence, which could never be mined without it.
+    pub fn remove_with_successors(&self, id: &[u8; 32]) -> Result<(), ()> {
+        self.get_lock_pool()?.remove_with_successors(id);
+        Ok(())
+    }
+
+    /// Drops the transactions whose nonce was already used on the chain.
+    pub fn remove_stale(&self, state: &WorldState) -> Result<(), ()> {
+        let mut pool = self.get_lock_pool()?;
+
+        let stale = pool
+            .transactions
+            .values()
+            .filter(|pending| {
+                pending.transaction.nonce < state.next_nonce(&pending.transaction.from)
+            })
+            .map(|pending| pending.transaction.id())
+            .collect::<Vec<_>>();
+
+        for id in stale {
+            pool.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    /// Admits the held transactions whose predecessors are now pending or on
+    /// the chain, dropping the ones the chain `state` made stale.
+    pub fn promote_held(&self, state: &WorldState, header: &BlockHeader) -> Result<(), ()> {
+        let mut pool = self.get_lock_pool()?;
+
+        let senders = pool.held.keys().copied().collect::<Vec<_>>();
+        for sender in senders {
+            pool.promote_held(&sender, state, header);
+        }
+
+        Ok(())
+    }
+
+    /// Drops the transactions the new chain `state` rejects in mining order,
+    /// along with their successors, which could never be mined.
+    pub fn remove_rejected(&self, state: &WorldState, header: &BlockHeader) -> Result<(), ()> {
+        let mut pool = self.get_lock_pool()?;
+        let mut state = state.clone();
+
+        let mut rejected = 0;
+        for id in pool.prioritised(usize::MAX) {
+            let Some(pending) = pool.transactions.get(&id) else {
+                continue;
+            };
+
+            if state
+                .apply_transaction(header, &pending.transaction)
+                .is_err()
+            {
+                rejected += pool.remove_with_successors(&id).len();
+            }
+        }
+
+        if rejected > 0 {
+            info!(
+                "Dropped {} pending transactions rejected by the state",
+                rejected
+            );
+        }
+
+        pool.pending_state = Some(state);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.get_lock_pool()
+            .map_or(0, |pool| pool.transactions.len())
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.get_lock_pool().map_or(true, |p| p.is_empty())
+        self.len() == 0
+    }
+
+    /// Encoded size of every pending transaction.
+    pub fn size_bytes(&self) -> usize {
+        self.get_lock_pool().map_or(0, |pool| pool.bytes)
     }
 
-    pub fn arc(&self) -> Arc<Mutex<VecDeque<Transaction>>> {
-        Arc::clone(&self.transaction_pool)
+    pub fn contains(&self, id: &[u8; 32]) -> bool {
+        self.get_lock_pool()
+            .is_ok_and(|pool| pool.transactions.contains_key(id))
+    }
+
+    pub fn get_transaction(&self, id: &[u8; 32]) -> Option<Transaction> {
+        let pool = self.get_lock_pool().ok()?;
+        pool.transactions
+            .get(id)
+            .map(|pending| pending.transaction.clone())
+    }
+
+    /// Pending transactions in the order the miner would pick them.
+    pub fn pending_transactions(&self) -> Vec<Transaction> {
+        let Ok(pool) = self.get_lock_pool() else {
+            return vec![];
+        };
+
+        pool.prioritised(usize::MAX)
+            .into_iter()
+            .filter_map(|id| pool.transactions.get(&id))
+            .map(|pending| pending.transaction.clone())
+            .collect()
+    }
+
+    /// Pending transactions of `account`, ordered by nonce.
+    pub fn pending_for(&self, account: &PublicKey) -> Vec<Transaction> {
+        let Ok(pool) = self.get_lock_pool() else {
+            return vec![];
+        };
+
+        pool.senders
+            .get(account)
+            .into_iter()
+            .flat_map(|nonces| nonces.values())
+            .filter_map(|id| pool.transactions.get(id))
+            .map(|pending| pending.transaction.clone())
+            .collect()
     }
 
+    /// Next batch to mine, the transactions stay pending until their block
+    /// is appended so a cancelled or stale block loses none of them.
     pub(crate) fn fetch_batch_transactions(
         &mut self,
         batch_size: usize,
     ) -> Result<Vec<Transaction>, ()> {
         let mut pool = self.get_lock_pool()?;
+        pool.remove_expired();
+
+        let batch = pool.prioritised(batch_size.min(block::MAX_TRANSACTION));
+
+        Ok(batch
+            .into_iter()
+            .filter_map(|id| pool.transactions.get(&id))
+            .map(|pending| pending.transaction.clone())
+            .collect())
+    }
+}
+
+impl Mempool {
+    fn next_nonce(&self, account: &PublicKey) -> Option<u32> {
+        let (nonce, _) = self.senders.get(account)?.last_key_value()?;
+        Some(nonce.saturating_add(1))
+    }
+
+    /// Admits `transaction` as the next one of its sender, see
+    /// [`TransactionPool::add_transaction`].
+    fn admit(
+        &mut self,
+        transaction: Transaction,
+        state: &WorldState,
+        header: &BlockHeader,
+    ) -> Result<(), TransactionPoolError> {
+        let id = transaction.id();
+        let invalid = |e| TransactionPoolError::InvalidTransaction(id, e);
+
+        let expected = self
+            .next_nonce(&transaction.from)
+            .unwrap_or_else(|| state.next_nonce(&transaction.from));
+
+        if transaction.nonce != expected {
+            return Err(invalid(TransactionError::InvalidNonce {
+                expected,
+                found: transaction.nonce,
+            }));
+        }
+
+        if self.pending_count(&transaction.from) >= self.limits.max_per_sender {
+            return Err(TransactionPoolError::SenderLimit);
+        }
+
+        self.pending_state(state, header)
+            .apply_transaction(header, &transaction)
+            .map_err(invalid)?;
+
+        self.insert(transaction, Instant::now());
+
+        if !self.evict_overflow().contains(&id) {
+            return Ok(());
+        }
+
+        Err(TransactionPoolError::PoolFull)
+    }
+
+    fn is_held(&self, transaction: &Transaction) -> bool {
+        self.held
+            .get(&transaction.from)
+            .and_then(|held| held.get(&transaction.nonce))
+            .is_some_and(|pending| pending.transaction.id() == transaction.id())
+    }
+
+    fn hold(&mut self, transaction: Transaction) -> Result<(), TransactionPoolError> {
+        let id = transaction.id();
+
+        if self.held.values().map(BTreeMap::len).sum::<usize>() >= self.limits.max_held {
+            return Err(TransactionPoolError::PoolFull);
+        }
+
+        let size = transaction.encode().len();
+        self.held.entry(transaction.from).or_default().insert(
+            transaction.nonce,
+            PendingTransaction {
+                transaction,
+                size,
+                received_at: Instant::now(),
+            },
+        );
+
+        Err(TransactionPoolError::Held(id))
+    }
+
+    /// Admits the held transactions of `sender` that are next in its sequence,
+    /// one after the other.
+    fn promote_held(&mut self, sender: &PublicKey, state: &WorldState, header: &BlockHeader) {
+        let next_nonce = self
+            .next_nonce(sender)
+            .unwrap_or_else(|| state.next_nonce(sender));
+
+        let Some(held) = self.held.get_mut(sender) else {
+            return;
+        };
+
+        *held = held.split_off(&next_nonce);
+        let Some(pending) = held.remove(&next_nonce) else {
+            if held.is_empty() {
+                self.held.remove(sender);
+            }
+            return;
+        };
+
+        let id = pending.transaction.id();
+        match self.admit(pending.transaction, state, header) {
+            Ok(()) => self.promote_held(sender, state, header),
+            Err(e) => info!("Held transaction {} rejected: {}", hex::encode(id), e),
+        }
+    }
+
+    fn pending_count(&self, account: &PublicKey) -> usize {
+        self.senders.get(account).map_or(0, |nonces| nonces.len())
+    }
+
+    fn insert(&mut self, transaction: Transaction, received_at: Instant) {
+        let id = transaction.id();
+        let size = transaction.encode().len();
+
+        self.senders
+            .entry(transaction.from)
+            .or_default()
+            .insert(transaction.nonce, id);
+
+        self.bytes += size;
+        self.transactions.insert(
+            id,
+            PendingTransaction {
+                transaction,
+                size,
+                received_at,
+            },
+        );
+    }
+
+    fn remove(&mut self, id: &Id) -> Option<Transaction> {
+        let pending = self.transactions.remove(id)?;
+        self.pending_state = None;
+        let from = pending.transaction.from;
+
+        if let Some(nonces) = self.senders.get_mut(&from) {
+            nonces.remove(&pending.transaction.nonce);
+
+            if nonces.is_empty() {
+                self.senders.remove(&from);
+            }
+        }
+
+        self.bytes -= pending.size;
+        Some(pending.transaction)
+    }
+
+    /// Removes `id` and the transactions following it in its sender's sequence,
+    /// which could never be mined without it.
+    fn remove_with_successors(&mut self, id: &Id) -> Vec<Id> {
+        let Some(pending) = self.transactions.get(id) else {
+            return vec![];
+        };
+
+        let successors = self
+            .senders
+            .get(&pending.transaction.from)
+            .map(|nonces| {
+                nonces
+                    .range(pending.transaction.nonce..)
+                    .map(|(_, id)| *id)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        for successor in &successors {
+            self.remove(successor);
+        }
+
+        successors
+    }
+
+    /// See [`Mempool::pending_state`], rebuilt from the chain `state` if needed.
+    fn pending_state(&mut self, state: &WorldState, header: &BlockHeader) -> &mut WorldState {
+        let pending_state = match self.pending_state.take() {
+            Some(pending_state) => pending_state,
+            None => {
+                let mut pending_state = state.clone();
+                for id in self.prioritised(usize::MAX) {
+                    // the rejected ones are dropped with the next block
+                    let _ = pending_state
+                        .apply_transaction(header, &self.transactions[&id].transaction);
+                }
+
+                pending_state
+            }
+        };
+
+        self.pending_state.insert(pending_state)
+    }
+
+    fn remove_expired(&mut self) {
+        let expiry = self.limits.expiry;
+        self.held.retain(|_, held| {
+            held.retain(|_, pending| pending.received_at.elapsed() <= expiry);
+            !held.is_empty()
+        });
+
+        let expired = self
+            .transactions
+            .iter()
+            .filter(|(_, pending)| pending.received_at.elapsed() > self.limits.expiry)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            let removed = self.remove_with_successors(&id);
+            if !removed.is_empty() {
+                info!("Expired {} pending transactions", removed.len());
+            }
+        }
+    }
+
+    /// Evicts the last transaction of the busiest sender until the pool fits
+    /// its limits, returning the evicted ids.
+    fn evict_overflow(&mut self) -> Vec<Id> {
+        let mut evicted = vec![];
+
+        while self.transactions.len() > self.limits.max_transactions
+            || self.bytes > self.limits.max_bytes
+        {
+            let Some(id) = self
+                .senders
+                .values()
+                .filter_map(|nonces| nonces.last_key_value().map(|(_, id)| (nonces.len(), id)))
+                .max_by_key(|(count, id)| (*count, self.transactions[*id].received_at))
+                .map(|(_, id)| *id)
+            else {
+                break;
+            };
+
+            self.remove(&id);
+            evicted.push(id);
+        }
+
+        evicted
+    }
+
+    /// Up to `limit` ids, oldest first, where every sender's transactions come
+    /// in nonce order.
+    fn prioritised(&self, limit: usize) -> Vec<Id> {
+        let mut queues = self
+            .senders
+            .values()
+            .map(|nonces| nonces.values())
+            .collect::<Vec<_>>();
+
+        let mut heads = BinaryHeap::new();
+        for (queue, ids) in queues.iter_mut().enumerate() {
+            if let Some(id) = ids.next() {
+                heads.push(Reverse((self.transactions[id].received_at, queue, *id)));
+            }
+        }
+
+        let mut batch = vec![];
+        while batch.len() < limit {
+            let Some(Reverse((_, queue, id))) = heads.pop() else {
+                break;
+            };
+
+            batch.push(id);
 
-        if pool.is_empty() {
-            return Ok(vec![]);
+            if let Some(next) = queues[queue].next() {
+                heads.push(Reverse((self.transactions[next].received_at, queue, *next)));
+            }
         }
 
-        let end = batch_size.min(block::MAX_TRANSACTION).min(pool.len());
-        Ok(pool.drain(0..end).collect::<Vec<_>>())
+        batch
     }
 }
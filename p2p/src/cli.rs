@@ -4,7 +4,10 @@ use clap::{Parser, ValueEnum};
 use serde::Deserialize;
 use thiserror::Error;
 
-use crate::kademlia::node::Contract;
+use crate::{
+    blockchain::{default_threads, ChainParams},
+    kademlia::node::Contract,
+};
 
 #[derive(Debug, Error)]
 pub enum CliError {
@@ -36,6 +39,10 @@ pub struct Arguments {
 
     #[arg(long)]
     pub config: Option<PathBuf>,
+
+    /// Threads searching proof of work nonces, one per core by default.
+    #[arg(long)]
+    pub miner_threads: Option<usize>,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, ValueEnum)]
@@ -60,6 +67,12 @@ pub struct Config {
     pub port: usize,
     bootstrap: Vec<String>,
     pub out: PathBuf,
+
+    #[serde(default)]
+    pub chain: ChainParams,
+
+    #[serde(default = "default_threads")]
+    pub miner_threads: usize,
 }
 
 impl Config {
@@ -97,6 +110,8 @@ impl Arguments {
                 port: 6657,
                 bootstrap: vec![],
                 out: "out.bin".into(),
+                chain: ChainParams::default(),
+                miner_threads: default_threads(),
             }
         };
 
@@ -106,6 +121,8 @@ impl Arguments {
             port: args.port.unwrap_or(file_config.port),
             bootstrap: args.bootstrap.unwrap_or(file_config.bootstrap),
             out: args.out.unwrap_or(file_config.out),
+            chain: file_config.chain,
+            miner_threads: args.miner_threads.unwrap_or(file_config.miner_threads),
         })
     }
 }
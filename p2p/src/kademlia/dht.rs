@@ -7,8 +7,10 @@ use std::{
 use thiserror::Error;
 use tokio::sync::Mutex;
 
+use crate::blockchain::PowWorkers;
 use crate::network::grpc::proto::{
-    find_value_response::Resp, FindNodeRequest, FindValueRequest, PingRequest, StoreRequest,
+    find_value_response::Resp, AnnounceRequest, FetchPendingRequest, FindNodeRequest,
+    FindValueRequest, PingRequest, StoreRequest,
 };
 
 use super::{
@@ -32,8 +34,14 @@ pub enum KademliaError {
 
     #[error("Failed to find value command")]
     FindValueFailedError,
+
+    #[error("Failed to announce value")]
+    AnnounceFailedError,
 }
 
+/// Most pending values a peer hands out on a single fetch.
+pub const MAX_PENDING_FETCH: usize = 1024;
+
 #[derive(Debug, Clone)]
 pub struct DHTNode {
     pub core: Node,
@@ -57,8 +65,9 @@ impl DHTNode {
         Some(dth)
     }
 
-    pub async fn join_network(&mut self, bootstrap: &Contract) -> Option<()> {
-        let Some(mut ticket) = NodeTicket::request_challange(&self.core, &bootstrap).await else {
+    pub async fn join_network(&mut self, bootstrap: &Contract, workers: &PowWorkers) -> Option<()> {
+        let Some(mut ticket) = NodeTicket::request_challange(&self.core, &bootstrap, workers).await
+        else {
             return None;
         };
 
@@ -188,6 +197,83 @@ impl DHTNode {
         };
     }
 
+    /// Gossips `value` to the closest known neighbours, without storing it.
+    pub async fn announce(&self, value: Box<dyn KademliaData>) -> Result<(), KademliaError> {
+        let neighbours = self.neighbours().await?;
+
+        let config = bincode::config::standard();
+        let Ok(encoded_data) = bincode::serde::encode_to_vec(&value, config) else {
+            return Err(KademliaError::AnnounceFailedError);
+        };
+
+        let mut has_announced = false;
+        for node in neighbours {
+            let Ok(mut client) = GrpcNetwork::connect_over(self.core.clone(), node).await else {
+                continue;
+            };
+
+            if client
+                .announce(AnnounceRequest {
+                    value: encoded_data.clone(),
+                })
+                .await
+                .is_ok()
+            {
+                has_announced = true;
+            }
+        }
+
+        if !has_announced {
+            return Err(KademliaError::AnnounceFailedError);
+        }
+
+        Ok(())
+    }
+
+    /// Values the closest known neighbours are still waiting to be stored.
+    pub async fn fetch_pending(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<Box<dyn KademliaData>>, KademliaError> {
+        let neighbours = self.neighbours().await?;
+        let limit = limit.min(MAX_PENDING_FETCH) as u32;
+        let config = bincode::config::standard();
+
+        let mut pending = vec![];
+        for node in neighbours {
+            let Ok(mut client) = GrpcNetwork::connect_over(self.core.clone(), node).await else {
+                continue;
+            };
+
+            let Ok(response) = client.fetch_pending(FetchPendingRequest { limit }).await else {
+                continue;
+            };
+
+            pending.extend(response.into_inner().values.iter().filter_map(|value| {
+                bincode::serde::decode_from_slice::<Box<dyn KademliaData>, _>(value, config)
+                    .ok()
+                    .map(|(value, _)| value)
+            }));
+        }
+
+        Ok(pending)
+    }
+
+    async fn neighbours(&self) -> Result<Vec<Node>, KademliaError> {
+        let routing_table = Arc::clone(&self.routing_table);
+        let Ok(mut routing_table) = routing_table.try_lock() else {
+            return Err(KademliaError::FailedAccessError);
+        };
+
+        Ok(routing_table
+            .get_closest_nodes(&self.core.id, KBUCKET_MAX)
+            .await
+            .into_iter()
+            .map(|NodeDistance(_, node)| node)
+            .filter(|node| node.id != self.core.id)
+            .collect())
+    }
+
     pub async fn node_lookup(&self, target_id: &NodeId) -> Result<Vec<Node>, KademliaError> {
         let routing_table = Arc::clone(&self.routing_table);
 
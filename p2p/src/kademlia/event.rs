@@ -2,14 +2,20 @@ use std::fmt::Debug;
 
 use tonic::async_trait;
 
-use super::data::KademliaData;
+use super::{data::KademliaData, Node};
 
 #[derive(Debug)]
 pub enum DHTEvent {
-    Store(Box<dyn KademliaData>),
+    Store(Node, Box<dyn KademliaData>),
+
+    /// Value gossiped by a peer without being stored in the table.
+    Announce(Node, Box<dyn KademliaData>),
 }
 
 #[async_trait]
 pub trait DHTEventHandler: Debug + Send + Sync {
     async fn on_event(&self, event: DHTEvent);
+
+    /// Values waiting to be announced, served to peers catching up.
+    async fn pending(&self, limit: usize) -> Vec<Box<dyn KademliaData>>;
 }
@@ -2,7 +2,7 @@ use std::{collections::HashMap, sync::Arc};
 
 use log::info;
 use rand::Rng;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
 use tonic::{Response, Status};
 
 use crate::{
@@ -10,15 +10,16 @@ use crate::{
     kademlia::NodeId,
     network::grpc::proto::{
         find_value_response::Resp, join_service_server::JoinService,
-        kademlia_service_server::KademliaService, ChallangeRequest, ChallangeResponse,
-        FindNodeRequest, FindNodeResponse, FindValueRequest, FindValueResponse, NodeInfo,
-        PingRequest, PongResponse, RepetedNode, StoreRequest, StoreResponse, SubmitRequest,
-        SubmitResponse,
+        kademlia_service_server::KademliaService, AnnounceRequest, ChallangeRequest,
+        ChallangeResponse, FetchPendingRequest, FetchPendingResponse, FindNodeRequest,
+        FindNodeResponse, FindValueRequest, FindValueResponse, NodeInfo, PingRequest, PongResponse,
+        RepetedNode, StoreRequest, StoreResponse, SubmitRequest, SubmitResponse,
     },
 };
 
 use super::{
     data::{KademliaData, Ticket},
+    dht::MAX_PENDING_FETCH,
     event::{DHTEvent, DHTEventHandler},
     routing_table::RoutingTable,
     signature::{HandleSignature, Signature},
@@ -26,6 +27,9 @@ use super::{
     Node, KBUCKET_MAX,
 };
 
+/// Announces waiting to be handled, further ones are refused until it drains.
+pub const MAX_QUEUED_ANNOUNCES: usize = 1024;
+
 #[derive(Debug, Clone)]
 pub(crate) struct GrpcNetwork {
     pub(crate) node: Node,
@@ -33,6 +37,10 @@ pub(crate) struct GrpcNetwork {
     pub(crate) distributed_hashing_table: Arc<Mutex<HashMap<NodeId, Box<dyn KademliaData>>>>,
 
     pub(crate) event_handler: Arc<dyn DHTEventHandler>,
+
+    /// Announces handled one at a time in arrival order, so a sender's
+    /// transactions reach the pool in the order they were gossiped.
+    announces: mpsc::Sender<(Node, Box<dyn KademliaData>)>,
 }
 
 impl GrpcNetwork {
@@ -42,11 +50,21 @@ impl GrpcNetwork {
         distributed_hashing_table: Arc<Mutex<HashMap<NodeId, Box<dyn KademliaData>>>>,
         event_handler: Arc<dyn DHTEventHandler>,
     ) -> Self {
+        let (announces, mut queue) = mpsc::channel(MAX_QUEUED_ANNOUNCES);
+
+        let handler = Arc::clone(&event_handler);
+        tokio::spawn(async move {
+            while let Some((sender, value)) = queue.recv().await {
+                handler.on_event(DHTEvent::Announce(sender, value)).await;
+            }
+        });
+
         Self {
             node,
             routing_table,
             distributed_hashing_table,
             event_handler,
+            announces,
         }
     }
 }
@@ -204,7 +222,7 @@ impl KademliaService for GrpcNetwork {
             let event_handler = Arc::clone(&self.event_handler);
 
             event_handler
-                .on_event(DHTEvent::Store(decoded_value.clone()))
+                .on_event(DHTEvent::Store(incoming_node, decoded_value.clone()))
                 .await;
         }
 
@@ -303,4 +321,50 @@ impl KademliaService for GrpcNetwork {
             resp: Some(Resp::Nodes(RepetedNode { nodes: response })),
         }))
     }
+
+    async fn announce(
+        &self,
+        request: tonic::Request<AnnounceRequest>,
+    ) -> Result<tonic::Response<()>, tonic::Status> {
+        let incoming_node = self.get_peer(&request)?;
+        let request = request.into_inner();
+
+        let config = bincode::config::standard();
+        let Ok((decoded_value, _)) =
+            bincode::serde::decode_from_slice::<Box<dyn KademliaData>, _>(&request.value, config)
+        else {
+            return Err(tonic::Status::aborted("Failed to decode value"));
+        };
+
+        let routing_table = Arc::clone(&self.routing_table);
+        if let Ok(mut routing_table) = routing_table.try_lock() {
+            routing_table.insert_node(&incoming_node).await;
+        }
+
+        // handled in the background, so gossip doesn't wait on the whole network
+        self.announces
+            .try_send((incoming_node, decoded_value))
+            .map_err(|_| tonic::Status::resource_exhausted("Too many pending announces"))?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn fetch_pending(
+        &self,
+        request: tonic::Request<FetchPendingRequest>,
+    ) -> Result<tonic::Response<FetchPendingResponse>, tonic::Status> {
+        let _peer = self.get_peer(&request)?;
+        let request = request.into_inner();
+
+        let limit = (request.limit as usize).min(MAX_PENDING_FETCH);
+        let pending = self.event_handler.pending(limit).await;
+
+        let config = bincode::config::standard();
+        let values = pending
+            .iter()
+            .filter_map(|value| bincode::serde::encode_to_vec(value, config).ok())
+            .collect::<Vec<_>>();
+
+        Ok(Response::new(FetchPendingResponse { values }))
+    }
 }
@@ -1,8 +1,11 @@
+use std::sync::atomic::AtomicBool;
+
 use ed25519_dalek::SIGNATURE_LENGTH;
+use log::error;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    blockchain::{DoubleHasher, HashFunc},
+    blockchain::{validate_hash, DoubleHasher, HashFunc, PowWorkers},
     kademlia::dht::KademliaError,
     network::grpc::proto::{ChallangeRequest, SubmitRequest},
     utils,
@@ -70,51 +73,38 @@ impl NodeTicket {
         nonce: u32,
         hasher: impl HashFunc,
     ) -> [u8; 32] {
-        let input = format!("{}{}{}", hex::encode(pub_key), challange, nonce);
-        hasher.hash(input)
-    }
+        let mut input = Vec::with_capacity(NODE_ID_LENGTH + 4 + 4);
 
-    pub fn validate_pow(hash: &[u8; 32], difficulty: u32) -> bool {
-        let nibbles = difficulty as usize;
-        let full_bytes = nibbles / 2;
-        let has_half_nibble = nibbles % 2 == 1;
-
-        for i in 0..full_bytes {
-            if hash[i] != 0 {
-                return false;
-            }
-        }
+        input.extend_from_slice(&pub_key);
+        input.extend_from_slice(&challange.to_le_bytes());
+        input.extend_from_slice(&nonce.to_le_bytes());
 
-        if has_half_nibble {
-            if (hash[full_bytes] >> 4) != 0 {
-                return false;
-            }
-        }
+        hasher.hash_bytes(&input)
+    }
 
-        true
+    pub fn validate_pow(hash: &[u8; 32], difficulty: u32) -> bool {
+        validate_hash(hash, difficulty)
     }
 
+    /// The challenge fixes every other input, so `None` means no nonce solves it.
     fn brute_force_pow(
         pub_key: [u8; 32],
         challange: u32,
         dificulty: u32,
-        hasher: impl HashFunc,
-    ) -> (u32, [u8; 32]) {
-        let mut prof_of_work: [u8; 32];
-        let mut nonce: u32 = 0;
-
-        loop {
-            prof_of_work = Self::calculate_pow(pub_key, challange, nonce, hasher.clone());
-
-            if Self::validate_pow(&prof_of_work, dificulty) {
-                return (nonce, prof_of_work);
-            }
-
-            nonce = nonce.wrapping_add(1);
-        }
+        hasher: impl HashFunc + Sync,
+        workers: &PowWorkers,
+    ) -> Option<(u32, [u8; 32])> {
+        workers.search(&AtomicBool::new(false), |nonce| {
+            let prof_of_work = Self::calculate_pow(pub_key, challange, nonce, hasher.clone());
+            Self::validate_pow(&prof_of_work, dificulty).then_some(prof_of_work)
+        })
     }
 
-    pub async fn request_challange(host: &Node, bootstrap: &Contract) -> Option<NodeTicket> {
+    pub async fn request_challange(
+        host: &Node,
+        bootstrap: &Contract,
+        workers: &PowWorkers,
+    ) -> Option<NodeTicket> {
         let Ok(mut client) = GrpcNetwork::handshake(Node::from_contract(bootstrap))
             .await
             .map_err(|_| {
@@ -134,12 +124,23 @@ impl NodeTicket {
             Err(_) => return None,
         };
 
-        let (nonce, pow) = Self::brute_force_pow(
-            host.keys.public_key,
-            response.challange,
-            response.difficulty,
-            DoubleHasher::default(),
-        );
+        let pub_key = host.keys.public_key;
+        let workers = workers.clone();
+        let solved = tokio::task::spawn_blocking(move || {
+            Self::brute_force_pow(
+                pub_key,
+                response.challange,
+                response.difficulty,
+                DoubleHasher::default(),
+                &workers,
+            )
+        })
+        .await;
+
+        let Ok(Some((nonce, pow))) = solved else {
+            error!("Failed to solve the join challenge");
+            return None;
+        };
 
         Some(NodeTicket::new(pow, response.challange, nonce))
     }
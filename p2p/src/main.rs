@@ -15,6 +15,13 @@ pub fn print_node_info(config: Config) {
     println!();
     println!("Type: {}", config.node_type.to_string());
     println!("Host: {} -> listening on {}", config.host, config.port);
+    println!(
+        "Chain: {:?}, one block every {}s, retargeting every {} blocks",
+        config.chain.consensus,
+        config.chain.target_block_interval,
+        config.chain.retarget_window()
+    );
+    println!("Mining on {} threads", config.miner_threads);
 
     if let Some(path) = config.out.to_str() {
         println!("Persisting at {}", path);
@@ -46,6 +53,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             bootstraps: args.get_bootstrap_nodes(),
             host: args.host,
             port: args.port,
+            chain_params: args.chain,
+            miner_threads: args.miner_threads,
         },
         storage.clone(),
     )
@@ -1,57 +1,106 @@
 use hex;
 use log::info;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use crate::blockchain::Transaction;
 
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
 pub struct MerkleTree {
     pub root: [u8; 32],
     pub levels: Vec<Vec<[u8; 32]>>,
+    legacy: bool,
+}
+
+/// Sibling path from a leaf up to the root, ordered from the leaf level.
+/// Levels where the node had no sibling, and was promoted as is, are `None`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<Option<[u8; 32]>>,
 }
 
 impl MerkleTree {
+    /// Tree over the canonical transaction encoding, leaves and inner nodes are
+    /// hashed under different prefixes and an odd node is promoted, never duplicated.
     pub fn from_transactions(txs: Vec<Transaction>) -> Self {
+        let leaves = txs.iter().map(Self::leaf_hash).collect::<Vec<_>>();
+
+        Self::build_tree(leaves, false)
+    }
+
+    /// Tree committed by blocks before the canonical encoding, hashing the JSON of
+    /// every transaction and duplicating the last node of odd levels.
+    pub fn from_transactions_legacy(txs: Vec<Transaction>) -> Self {
         let leaves = txs
             .iter()
-            .map(|transaction| Self::hash(transaction.to_json_string()))
+            .map(|transaction| Self::hash_legacy(transaction.to_json_string()))
             .collect::<Vec<_>>();
 
-        Self::build_tree(leaves)
+        Self::build_tree(leaves, true)
+    }
+
+    pub fn leaf_hash(transaction: &Transaction) -> [u8; 32] {
+        Self::hash_leaf(&transaction.encode())
+    }
+
+    pub fn hash_leaf(data: &[u8]) -> [u8; 32] {
+        Self::hash(LEAF_PREFIX, data)
     }
 
-    fn build_tree(mut current_level: Vec<[u8; 32]>) -> Self {
+    fn build_tree(mut current_level: Vec<[u8; 32]>, legacy: bool) -> Self {
         let mut levels = vec![current_level.clone()];
 
         if current_level.is_empty() {
             return MerkleTree {
                 root: [0u8; 32],
                 levels: vec![],
+                legacy,
             };
         }
 
         while current_level.len() > 1 {
-            current_level = Self::next_level(&current_level);
+            current_level = if legacy {
+                Self::next_level_legacy(&current_level)
+            } else {
+                Self::next_level(&current_level)
+            };
+
             levels.push(current_level.clone());
         }
 
         MerkleTree {
             root: current_level[0],
             levels,
+            legacy,
         }
     }
 
     fn next_level(prev_level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+        prev_level
+            .chunks(2)
+            .map(|chunk| match chunk {
+                [left, right] => Self::hash_pair(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn next_level_legacy(prev_level: &[[u8; 32]]) -> Vec<[u8; 32]> {
         prev_level
             .chunks(2)
             .map(|chunk| {
                 if chunk.len() == 2 {
-                    Self::hash(format!(
+                    Self::hash_legacy(format!(
                         "{}{}",
                         hex::encode(chunk[0]),
                         hex::encode(chunk[1])
                     ))
                 } else {
-                    Self::hash(format!(
+                    Self::hash_legacy(format!(
                         "{}{}",
                         hex::encode(chunk[0]),
                         hex::encode(chunk[0])
@@ -61,15 +110,71 @@ impl MerkleTree {
             .collect()
     }
 
-    fn hash(data: String) -> [u8; 32] {
+    pub(crate) fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut data = Vec::with_capacity(64);
+        data.extend_from_slice(left);
+        data.extend_from_slice(right);
+
+        Self::hash(NODE_PREFIX, &data)
+    }
+
+    fn hash(prefix: u8, data: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update([prefix]);
+        hasher.update(data);
+        hasher.finalize().into()
+    }
+
+    fn hash_legacy(data: String) -> [u8; 32] {
         let mut hasher = Sha256::new();
         hasher.update(data);
         hasher.finalize().try_into().expect("msg")
     }
 
+    /// Proof that the leaf at `index` is part of this tree, legacy trees have none.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if self.legacy {
+            return None;
+        }
+
+        let leaves = self.levels.first()?;
+        if index >= leaves.len() {
+            return None;
+        }
+
+        let mut siblings = vec![];
+        let mut position = index;
+
+        // the root level has no sibling
+        for level in &self.levels[..self.levels.len() - 1] {
+            siblings.push(level.get(position ^ 1).copied());
+            position /= 2;
+        }
+
+        Some(MerkleProof { index, siblings })
+    }
+
     pub fn print_tree(&self) {
         for (i, level) in self.levels.iter().enumerate().rev() {
             info!("Level {}: {:?}", i, level);
         }
     }
 }
+
+/// Checks `leaf` hashes up to `root` along the sibling path of `proof`.
+pub fn verify_proof(leaf: [u8; 32], proof: &MerkleProof, root: [u8; 32]) -> bool {
+    let mut position = proof.index;
+    let mut current = leaf;
+
+    for sibling in &proof.siblings {
+        current = match sibling {
+            Some(sibling) if position.is_multiple_of(2) => MerkleTree::hash_pair(&current, sibling),
+            Some(sibling) => MerkleTree::hash_pair(sibling, &current),
+            None => current,
+        };
+
+        position /= 2;
+    }
+
+    position == 0 && current == root
+}
@@ -1,3 +1,5 @@
 mod merlke_tree;
+mod sparse_merkle_tree;
 
-pub use merlke_tree::MerkleTree;
+pub use merlke_tree::{verify_proof, MerkleProof, MerkleTree};
+pub use sparse_merkle_tree::{verify_sparse_proof, SparseMerkleProof, SparseMerkleTree};
@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::MerkleTree;
+
+type Hash = [u8; 32];
+
+/// Root of a tree without leaves.
+const EMPTY_HASH: Hash = [0u8; 32];
+
+/// Key-value map committed by a sparse Merkle tree over the hash of every key.
+///
+/// Subtrees holding a single leaf collapse into it, so only the levels where
+/// two keys diverge get a branch and updates touch one path of nodes. Nodes
+/// are shared between clones, cloning the tree is cheap.
+#[derive(Clone, Debug, Default)]
+pub struct SparseMerkleTree {
+    root: Option<Arc<Node>>,
+    len: usize,
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(Leaf),
+    Branch {
+        left: Option<Arc<Node>>,
+        right: Option<Arc<Node>>,
+        hash: Hash,
+    },
+}
+
+#[derive(Debug)]
+struct Leaf {
+    path: Hash,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    hash: Hash,
+}
+
+/// Sibling of every branch from the root down to a leaf, empty subtrees
+/// hash to zero.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SparseMerkleProof {
+    pub siblings: Vec<Hash>,
+}
+
+impl SparseMerkleTree {
+    pub fn root(&self) -> Hash {
+        hash_of(&self.root)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.leaf(&path(key)).map(|leaf| leaf.value.as_slice())
+    }
+
+    /// Stores `value` at `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        let leaf = Leaf::new(key, value);
+        let (root, previous) = insert(self.root.as_ref(), leaf, 0);
+
+        self.root = Some(root);
+        if previous.is_none() {
+            self.len += 1;
+        }
+
+        previous
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let (root, previous) = remove(self.root.as_ref()?, &path(key), 0)?;
+
+        self.root = root;
+        self.len -= 1;
+
+        Some(previous)
+    }
+
+    /// Every entry, ordered by the hash of its key.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            stack: self.root.iter().map(Arc::as_ref).collect(),
+        }
+    }
+
+    /// Proof that `key` is part of the tree, see [`verify_sparse_proof`].
+    pub fn proof(&self, key: &[u8]) -> Option<SparseMerkleProof> {
+        let path = path(key);
+        let mut siblings = vec![];
+        let mut node = self.root.as_deref();
+
+        while let Some(current) = node {
+            match current {
+                Node::Leaf(leaf) if leaf.path == path => {
+                    return Some(SparseMerkleProof { siblings });
+                }
+                Node::Leaf(_) => return None,
+                Node::Branch { left, right, .. } => {
+                    let (next, sibling) = if bit(&path, siblings.len()) {
+                        (right, left)
+                    } else {
+                        (left, right)
+                    };
+
+                    siblings.push(hash_of(sibling));
+                    node = next.as_deref();
+                }
+            }
+        }
+
+        None
+    }
+
+    fn leaf(&self, path: &Hash) -> Option<&Leaf> {
+        let mut node = self.root.as_deref()?;
+        let mut depth = 0;
+
+        loop {
+            match node {
+                Node::Leaf(leaf) => return (leaf.path == *path).then_some(leaf),
+                Node::Branch { left, right, .. } => {
+                    node = if bit(path, depth) { right } else { left }.as_deref()?;
+                    depth += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Checks the entry `key` = `value` hashes up to `root` along `proof`.
+pub fn verify_sparse_proof(
+    key: &[u8],
+    value: &[u8],
+    proof: &SparseMerkleProof,
+    root: Hash,
+) -> bool {
+    let path = path(key);
+
+    let computed = proof.siblings.iter().enumerate().rev().fold(
+        leaf_hash(&path, value),
+        |current, (depth, sibling)| {
+            if bit(&path, depth) {
+                MerkleTree::hash_pair(sibling, &current)
+            } else {
+                MerkleTree::hash_pair(&current, sibling)
+            }
+        },
+    );
+
+    computed == root
+}
+
+pub struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            match node {
+                Node::Leaf(leaf) => return Some((&leaf.key, &leaf.value)),
+                Node::Branch { left, right, .. } => {
+                    self.stack.extend(right.as_deref());
+                    self.stack.extend(left.as_deref());
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Leaf {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        let path = path(&key);
+        let hash = leaf_hash(&path, &value);
+
+        Leaf {
+            path,
+            key,
+            value,
+            hash,
+        }
+    }
+}
+
+impl Node {
+    fn hash(&self) -> Hash {
+        match self {
+            Node::Leaf(leaf) => leaf.hash,
+            Node::Branch { hash, .. } => *hash,
+        }
+    }
+
+    fn branch(left: Option<Arc<Node>>, right: Option<Arc<Node>>) -> Arc<Node> {
+        let hash = MerkleTree::hash_pair(&hash_of(&left), &hash_of(&right));
+
+        Arc::new(Node::Branch { left, right, hash })
+    }
+
+    /// Branch over `left` and `right`, or the leaf itself when it's alone.
+    fn collapse(left: Option<Arc<Node>>, right: Option<Arc<Node>>) -> Option<Arc<Node>> {
+        match (left, right) {
+            (None, None) => None,
+            (Some(node), None) | (None, Some(node)) if matches!(*node, Node::Leaf(_)) => Some(node),
+            (left, right) => Some(Node::branch(left, right)),
+        }
+    }
+}
+
+fn insert(node: Option<&Arc<Node>>, leaf: Leaf, depth: usize) -> (Arc<Node>, Option<Vec<u8>>) {
+    let Some(node) = node else {
+        return (Arc::new(Node::Leaf(leaf)), None);
+    };
+
+    match node.as_ref() {
+        Node::Leaf(existing) if existing.path == leaf.path => {
+            let previous = existing.value.clone();
+            (Arc::new(Node::Leaf(leaf)), Some(previous))
+        }
+        Node::Leaf(existing) => {
+            let existing_path = existing.path;
+            (split(Arc::clone(node), &existing_path, leaf, depth), None)
+        }
+        Node::Branch { left, right, .. } => {
+            if bit(&leaf.path, depth) {
+                let (right, previous) = insert(right.as_ref(), leaf, depth + 1);
+                (Node::branch(left.clone(), Some(right)), previous)
+            } else {
+                let (left, previous) = insert(left.as_ref(), leaf, depth + 1);
+                (Node::branch(Some(left), right.clone()), previous)
+            }
+        }
+    }
+}
+
+/// Branches down from `depth` until the paths of `existing` and `leaf` diverge.
+fn split(existing: Arc<Node>, existing_path: &Hash, leaf: Leaf, depth: usize) -> Arc<Node> {
+    let goes_right = bit(&leaf.path, depth);
+
+    if bit(existing_path, depth) == goes_right {
+        let child = Some(split(existing, existing_path, leaf, depth + 1));
+        return if goes_right {
+            Node::branch(None, child)
+        } else {
+            Node::branch(child, None)
+        };
+    }
+
+    let leaf = Some(Arc::new(Node::Leaf(leaf)));
+    if goes_right {
+        Node::branch(Some(existing), leaf)
+    } else {
+        Node::branch(leaf, Some(existing))
+    }
+}
+
+/// Subtree without the leaf at `path` and its value, `None` when it's missing.
+fn remove(node: &Arc<Node>, path: &Hash, depth: usize) -> Option<(Option<Arc<Node>>, Vec<u8>)> {
+    match node.as_ref() {
+        Node::Leaf(leaf) if leaf.path == *path => Some((None, leaf.value.clone())),
+        Node::Leaf(_) => None,
+        Node::Branch { left, right, .. } => {
+            if bit(path, depth) {
+                let (right, previous) = remove(right.as_ref()?, path, depth + 1)?;
+                Some((Node::collapse(left.clone(), right), previous))
+            } else {
+                let (left, previous) = remove(left.as_ref()?, path, depth + 1)?;
+                Some((Node::collapse(left, right.clone()), previous))
+            }
+        }
+    }
+}
+
+fn hash_of(node: &Option<Arc<Node>>) -> Hash {
+    node.as_ref().map_or(EMPTY_HASH, |node| node.hash())
+}
+
+fn path(key: &[u8]) -> Hash {
+    Sha256::digest(key).into()
+}
+
+/// Leaf of an entry, the path commits to the key.
+fn leaf_hash(path: &Hash, value: &[u8]) -> Hash {
+    let mut bytes = Vec::with_capacity(path.len() + value.len());
+    bytes.extend_from_slice(path);
+    bytes.extend_from_slice(value);
+
+    MerkleTree::hash_leaf(&bytes)
+}
+
+/// Bit of `path` at `depth`, most significant first.
+fn bit(path: &Hash, depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
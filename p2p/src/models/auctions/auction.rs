@@ -1,4 +1,3 @@
-use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
@@ -12,6 +11,51 @@ pub enum AuctionError {
 
     #[error("Failed to fetch highest bid")]
     FailedToFetchHighestBid,
+
+    #[error("Auction doesn't exist")]
+    UnknownAuction,
+
+    #[error("Auction already exists")]
+    AlreadyExists,
+
+    #[error("Auction is closed")]
+    Closed,
+
+    #[error("Only the seller may close the auction")]
+    NotSeller,
+
+    #[error("Sellers can't bid on their own auction")]
+    OwnAuction,
+
+    #[error("Bid must exceed the current and start prices")]
+    BidTooLow,
+
+    #[error("Auction deadline has already passed")]
+    DeadlinePassed,
+
+    #[error("Extension window requires a timestamp deadline")]
+    ExtensionWithoutDeadline,
+
+    #[error("Extension window must be positive")]
+    InvalidExtensionWindow,
+}
+
+/// When an auction closes on its own, checked against the block carrying each
+/// bid so the block reaching a height is the last one accepting bids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Deadline {
+    Height(u64),
+    Timestamp(Timestamp),
+}
+
+impl Deadline {
+    /// Whether the block at `height` and `timestamp` comes after the deadline.
+    pub fn is_passed_by(&self, height: u64, timestamp: Timestamp) -> bool {
+        match *self {
+            Deadline::Height(deadline) => height > deadline,
+            Deadline::Timestamp(deadline) => timestamp >= deadline,
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -26,6 +70,11 @@ pub struct Auction {
     pub started_at: Timestamp,
     pub cancel_at: Timestamp,
     pub ended_at: Timestamp,
+    pub deadline: Option<Deadline>,
+
+    /// Bids placed this many seconds before the deadline push it back to as
+    /// much after the bid, so there is always time left to outbid them.
+    pub extension_window: Option<Timestamp>,
 }
 
 impl std::fmt::Debug for Auction {
@@ -41,20 +90,25 @@ impl std::fmt::Debug for Auction {
             .field("started_at", &self.started_at)
             .field("cancel_at", &self.cancel_at)
             .field("ended_at", &self.ended_at)
+            .field("deadline", &self.deadline)
+            .field("extension_window", &self.extension_window)
             .finish()
     }
 }
 
 impl Auction {
+    /// Prefix of the world state keys auctions are stored at.
+    pub const STATE_PREFIX: &'static [u8] = b"auction/";
+
     pub fn new(
         id: Uuid,
         seller: PublicKey,
         item: Item,
         start_price: Currency,
         goal_price: Currency,
+        timestamp: Timestamp,
+        deadline: Option<Deadline>,
     ) -> Self {
-        let timestamp = Utc::now().timestamp();
-
         Self {
             id,
             seller,
@@ -66,15 +120,26 @@ impl Auction {
             started_at: timestamp,
             cancel_at: 0,
             ended_at: 0,
+            deadline,
+            extension_window: None,
         }
     }
 
-    pub fn terminate(&mut self) {
-        self.ended_at = Utc::now().timestamp()
+    pub fn with_extension_window(mut self, extension_window: Option<Timestamp>) -> Self {
+        self.extension_window = extension_window;
+        self
     }
 
-    pub fn cancel(&mut self) {
-        self.cancel_at = Utc::now().timestamp()
+    pub fn state_key(id: &Uuid) -> Vec<u8> {
+        [Self::STATE_PREFIX, id.as_bytes()].concat()
+    }
+
+    pub fn terminate(&mut self, timestamp: Timestamp) {
+        self.ended_at = timestamp
+    }
+
+    pub fn cancel(&mut self, timestamp: Timestamp) {
+        self.cancel_at = timestamp
     }
 
     pub fn get_state(&self) -> String {
@@ -101,6 +166,72 @@ impl Auction {
         !self.is_canceled() || !self.is_terminated()
     }
 
+    fn is_open(&self) -> bool {
+        !self.is_canceled() && !self.is_terminated()
+    }
+
+    /// Whether the block at `height` and `timestamp` comes after the deadline of
+    /// the auction, while it's still open.
+    pub fn is_due(&self, height: u64, timestamp: Timestamp) -> bool {
+        self.is_open()
+            && self
+                .deadline
+                .is_some_and(|deadline| deadline.is_passed_by(height, timestamp))
+    }
+
+    /// Terminates the auction if the block at `height` and `timestamp` comes
+    /// after its deadline. Deadlines are never stored as reached, so it ends
+    /// at the deadline itself, or at the timestamp `deadline_block` gives for
+    /// the block at a height deadline, whatever tip it's read at.
+    pub fn close_if_due(
+        &mut self,
+        height: u64,
+        timestamp: Timestamp,
+        deadline_block: impl FnOnce(u64) -> Option<Timestamp>,
+    ) {
+        if !self.is_due(height, timestamp) {
+            return;
+        }
+
+        match self.deadline {
+            Some(Deadline::Timestamp(deadline)) => self.terminate(deadline),
+            Some(Deadline::Height(deadline)) => {
+                self.terminate(deadline_block(deadline).unwrap_or(timestamp))
+            }
+            None => {}
+        }
+    }
+
+    /// Whether `buyer` may bid `amount`, as enforced by every node.
+    pub fn check_bid(&self, buyer: &PublicKey, amount: Currency) -> Result<(), AuctionError> {
+        if !self.is_open() {
+            return Err(AuctionError::Closed);
+        }
+
+        if *buyer == self.seller {
+            return Err(AuctionError::OwnAuction);
+        }
+
+        if amount <= self.current_price || amount <= self.start_price {
+            return Err(AuctionError::BidTooLow);
+        }
+
+        Ok(())
+    }
+
+    /// Whether `account` may cancel or terminate the auction, as enforced by every node.
+    pub fn check_close(&self, account: &PublicKey) -> Result<(), AuctionError> {
+        if *account != self.seller {
+            return Err(AuctionError::NotSeller);
+        }
+
+        if !self.is_open() {
+            return Err(AuctionError::Closed);
+        }
+
+        Ok(())
+    }
+
     fn update_current_price(&mut self) {
         let Some(highest_bid) = self.get_highest_bid() else {
             return;
@@ -124,10 +255,26 @@ impl Auction {
             }
         }
 
+        self.extend_deadline(bid.created_at);
         self.history.push(bid);
         self.update_current_price();
     }
 
+    /// Moves the deadline to `window` seconds after a bid placed at `timestamp`,
+    /// if it falls within the extension window. Bids at or past the deadline
+    /// never reopen the auction.
+    fn extend_deadline(&mut self, timestamp: Timestamp) {
+        let (Some(Deadline::Timestamp(deadline)), Some(window)) =
+            (self.deadline, self.extension_window)
+        else {
+            return;
+        };
+
+        if (deadline.saturating_sub(window)..deadline).contains(&timestamp) {
+            self.deadline = Some(Deadline::Timestamp(timestamp.saturating_add(window)));
+        }
+    }
+
     pub fn get_highest_bid(&self) -> Option<Bid> {
         self.history.iter().cloned().max_by(|a, b| {
             a.amount
@@ -154,3 +301,63 @@ impl Auction {
         Ok(highest_bid)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEADLINE: Timestamp = 1_000;
+    const WINDOW: Timestamp = 60;
+
+    fn auction() -> Auction {
+        Auction::new(
+            Uuid::new_v4(),
+            [0; 32],
+            Item::new("item".into(), "".into()),
+            10,
+            100,
+            0,
+            Some(Deadline::Timestamp(DEADLINE)),
+        )
+        .with_extension_window(Some(WINDOW))
+    }
+
+    fn bid_at(auction: &mut Auction, amount: Currency, created_at: Timestamp) {
+        auction.add_bid(Bid::new(
+            Uuid::new_v4(),
+            [1; 32],
+            auction.id,
+            amount,
+            created_at,
+        ));
+    }
+
+    #[test]
+    fn bid_within_window_extends_deadline() {
+        let mut auction = auction();
+        bid_at(&mut auction, 20, DEADLINE - 10);
+
+        assert_eq!(
+            auction.deadline,
+            Some(Deadline::Timestamp(DEADLINE - 10 + WINDOW))
+        );
+    }
+
+    #[test]
+    fn bid_before_window_keeps_deadline() {
+        let mut auction = auction();
+        bid_at(&mut auction, 20, DEADLINE - WINDOW - 1);
+
+        assert_eq!(auction.deadline, Some(Deadline::Timestamp(DEADLINE)));
+    }
+
+    #[test]
+    fn bid_at_or_after_deadline_keeps_deadline() {
+        for created_at in [DEADLINE, DEADLINE + 1, DEADLINE + WINDOW] {
+            let mut auction = auction();
+            bid_at(&mut auction, 20, created_at);
+
+            assert_eq!(auction.deadline, Some(Deadline::Timestamp(DEADLINE)));
+        }
+    }
+}
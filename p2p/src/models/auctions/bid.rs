@@ -1,4 +1,3 @@
-use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -26,9 +25,13 @@ impl std::fmt::Debug for Bid {
 }
 
 impl Bid {
-    pub fn new(id: Uuid, buyer: PublicKey, auction_id: Uuid, amount: Currency) -> Self {
-        let created_at = Utc::now().timestamp();
-
+    pub fn new(
+        id: Uuid,
+        buyer: PublicKey,
+        auction_id: Uuid,
+        amount: Currency,
+        created_at: Timestamp,
+    ) -> Self {
         Self {
             id,
             auction_id,
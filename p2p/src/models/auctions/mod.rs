@@ -4,6 +4,11 @@ pub mod auction;
 pub mod bid;
 pub mod item;
 
-type Timestamp = i64;
+pub type Timestamp = i64;
 pub type Currency = u32;
 pub type PublicKey = [u8; NODE_ID_LENGTH];
+
+/// Seconds since the Unix epoch of a block timestamp, the clock auctions run on.
+pub fn block_timestamp(nanos: u128) -> Timestamp {
+    (nanos / 1_000_000_000) as Timestamp
+}
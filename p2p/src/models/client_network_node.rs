@@ -1,10 +1,11 @@
 use std::{
-    collections::HashMap,
     io::{stdout, Write},
     sync::Arc,
+    time::Duration,
     usize,
 };
 
+use chrono::{DateTime, Utc};
 use crossterm::{
     event::{self, Event, KeyCode},
     style, terminal,
@@ -14,18 +15,27 @@ use log::info;
 use uuid::Uuid;
 
 use crate::{
-    blockchain::Transaction,
+    blockchain::{BlockChain, Transaction},
     kademlia::secret_key::SecretPair,
     models::transactions::{CancelAuction, EndAuction},
     term::{self, TermError},
 };
 
 use super::{
-    auctions::{auction::Auction, bid::Bid, item::Item, Currency},
+    auctions::{
+        auction::{Auction, Deadline},
+        block_timestamp,
+        item::Item,
+        Currency, Timestamp,
+    },
     network_node::NetworkNode,
     transactions::{AuctionTransaction, CreateAuction, PlaceBid},
 };
 
+/// How often an open auction view reloads the auction from the local chain
+/// when no key is pressed.
+const AUCTION_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
 pub struct ClientNetworkNode {
     pub network_node: Arc<NetworkNode>,
     pub key_pair: SecretPair,
@@ -49,7 +59,6 @@ impl ClientNetworkNode {
     }
 
     async fn append_transaction(&self, tx_action: AuctionTransaction) -> Option<Transaction> {
-        let transaction = Transaction::new(self.key_pair.clone(), tx_action)?;
         let block_chain = Arc::clone(&self.network_node.block_chain);
 
         {
@@ -57,12 +66,18 @@ impl ClientNetworkNode {
                 return None;
             };
 
-            match block_tx
-                .transaction_poll
-                .add_transaction(transaction.clone())
-            {
-                Ok(_) => Some(transaction),
-                Err(_) => None,
+            let nonce = block_tx.next_nonce(&self.key_pair.public_key);
+            let transaction = Transaction::new(self.key_pair.clone(), nonce, tx_action)?;
+
+            match block_tx.submit_transaction(transaction.clone()) {
+                Ok(_) => {
+                    self.network_node.announce_transaction(&transaction).await;
+                    Some(transaction)
+                }
+                Err(e) => {
+                    info!("Failed to add transaction to the pool: {}", e);
+                    None
+                }
             }
         }
     }
@@ -72,6 +87,8 @@ impl ClientNetworkNode {
         item: Item,
         start_price: Currency,
         goal_price: Currency,
+        deadline: Option<Deadline>,
+        extension_window: Option<Timestamp>,
     ) -> Option<Transaction> {
         if start_price <= 0 || goal_price <= 0 {
             info!("Start price and goal price must be greater than zero.");
@@ -83,12 +100,17 @@ impl ClientNetworkNode {
             return None;
         }
 
-        self.append_transaction(AuctionTransaction::Create(CreateAuction::new(
-            item,
-            start_price,
-            goal_price,
-        )))
-        .await
+        let mut create_auction = CreateAuction::new(item, start_price, goal_price);
+        if let Some(deadline) = deadline {
+            create_auction = create_auction.with_deadline(deadline);
+        }
+
+        if let Some(extension_window) = extension_window {
+            create_auction = create_auction.with_extension_window(extension_window);
+        }
+
+        self.append_transaction(AuctionTransaction::Create(create_auction))
+            .await
     }
 
     pub async fn bid_on_auction(&self, auction_id: Uuid, amount: Currency) -> Option<Transaction> {
@@ -108,74 +130,48 @@ impl ClientNetworkNode {
             .await
     }
 
+    /// Syncs with the network, then reads the auctions off the chain state.
     pub async fn get_auctions(&self) -> Vec<Auction> {
-        let mut auctions: HashMap<Uuid, Auction> = HashMap::new();
-        let block_chain = Arc::clone(&self.network_node.block_chain);
+        // the sync appends to the chain, so it must not be locked meanwhile
+        if let Err(_) = self.network_node.sync().await {
+            info!("Failed to sync the blockchain.");
+        }
 
-        {
-            let Ok(block_chain) = block_chain.try_lock() else {
-                return vec![];
-            };
+        let Ok(block_chain) = self.network_node.block_chain.try_lock() else {
+            return vec![];
+        };
 
-            if let Err(_) = self.network_node.sync().await {
-                info!("Failed to sync the blockchain.");
-            }
+        block_chain
+            .state()
+            .scan_prefix_as(Auction::STATE_PREFIX)
+            .map(|auction| Self::close_if_due(&block_chain, auction))
+            .collect()
+    }
 
-            for block in block_chain.search_blocks_on(|_| true) {
-                for (transaction, auction) in block.get_transaction::<AuctionTransaction>() {
-                    match auction {
-                        AuctionTransaction::Create(create_auction) => {
-                            let auction = Auction::new(
-                                create_auction.id,
-                                transaction.from,
-                                create_auction.item.clone(),
-                                create_auction.start_price,
-                                create_auction.goal_price,
-                            );
-
-                            auctions.insert(auction.id, auction);
-                        }
-                        AuctionTransaction::Bid(place_bid) => {
-                            let bid = Bid::new(
-                                place_bid.id,
-                                transaction.from,
-                                place_bid.auction_id,
-                                place_bid.amount,
-                            );
-
-                            let Some(auction) = auctions.get_mut(&bid.auction_id) else {
-                                continue;
-                            };
-
-                            auction.add_bid(bid);
-                        }
-                        AuctionTransaction::Cancel(cancel_auction) => {
-                            let Some(auction) = auctions.get_mut(&cancel_auction.auction_id) else {
-                                continue;
-                            };
+    /// Reads `auction_id` as of the local tip without syncing, the chain is only
+    /// locked for as long as it takes. `None` when it's unknown or the chain is busy.
+    pub fn read_auction(&self, auction_id: Uuid) -> Option<Auction> {
+        let block_chain = self.network_node.block_chain.try_lock().ok()?;
+        let auction = block_chain
+            .state()
+            .get_as(&Auction::state_key(&auction_id))?;
 
-                            auction.cancel();
-                        }
-                        AuctionTransaction::End(end_auction) => {
-                            let Some(auction) = auctions.get_mut(&end_auction.auction_id) else {
-                                continue;
-                            };
+        Some(Self::close_if_due(&block_chain, auction))
+    }
 
-                            auction.terminate();
-                        }
-                    }
-                }
-            }
-        }
+    fn close_if_due(block_chain: &BlockChain, mut auction: Auction) -> Auction {
+        // deadlines are checked as the next block would, which comes after the tip
+        let state = block_chain.state();
+        let height = state.height() + 1;
+        let timestamp = block_timestamp(state.timestamp());
 
-        auctions.values().cloned().collect()
-    }
+        auction.close_if_due(height, timestamp, |height| {
+            block_chain
+                .get_block_by_height(height)
+                .map(|block| block_timestamp(block.header.timestamp))
+        });
 
-    pub async fn get_auction(&self, auction_id: Uuid) -> Option<Auction> {
-        self.get_auctions()
-            .await
-            .into_iter()
-            .find(|auction| auction.id == auction_id)
+        auction
     }
 
     // If is client do a check auth thinghy?
@@ -220,11 +216,22 @@ impl ClientNetworkNode {
         term::hide_cursor(true)?;
         terminal::enable_raw_mode()?;
 
+        let mut auction = auction.clone();
+
         loop {
+            // late bids may have pushed the deadline back since it was listed
+            if let Some(latest) = self.read_auction(auction.id) {
+                auction = latest;
+            }
+
             term::reset()?;
             term::print_title("===  Auction  ===", style::Color::Cyan)?;
 
             term::move_cursor(0, 4)?;
+            term::println(
+                format!("Ends: {}", Self::format_deadline(&auction)).as_str(),
+                style::Color::Grey,
+            )?;
             term::println("Incoming Bids", style::Color::Grey)?;
 
             if auction.is_terminated() {
@@ -260,10 +267,10 @@ impl ClientNetworkNode {
                 )?;
             }
 
-            term::move_cursor(0, 11)?;
+            term::move_cursor(0, 12)?;
             term::println(
                 format!(
-                    "## {} {} Exit - <Q>",
+                    "## {} {} Refresh - <R> || Exit - <Q>",
                     if auction.seller != self.key_pair.public_key {
                         "Bid - <B> || "
                     } else {
@@ -283,9 +290,18 @@ impl ClientNetworkNode {
 
             stdout.flush()?;
 
+            if !event::poll(AUCTION_REFRESH_INTERVAL)? {
+                continue;
+            }
+
             if let Event::Key(key_event) = event::read()? {
                 match key_event.code {
                     KeyCode::Char('q') => break,
+                    KeyCode::Char('r') => {
+                        if let Err(_) = self.network_node.sync().await {
+                            info!("Failed to sync the blockchain.");
+                        }
+                    }
                     KeyCode::Char('t') => {
                         if auction.seller != self.key_pair.public_key {
                             term::println(
@@ -295,7 +311,7 @@ impl ClientNetworkNode {
                             continue;
                         }
 
-                        term::move_cursor(0, 13)?;
+                        term::move_cursor(0, 14)?;
 
                         if let Some(_) = self.terminate_auction(auction.id).await {
                             info!("Auction {} terminated successfully", auction.id);
@@ -312,7 +328,7 @@ impl ClientNetworkNode {
                             continue;
                         }
 
-                        term::move_cursor(0, 13)?;
+                        term::move_cursor(0, 14)?;
 
                         if let Some(_) = self.cancel_auction(auction.id).await {
                             info!("Auction {} canceled successfully", auction.id);
@@ -330,7 +346,7 @@ impl ClientNetworkNode {
                             continue;
                         }
 
-                        term::move_cursor(0, 13)?;
+                        term::move_cursor(0, 14)?;
 
                         let amount = match self.set_amount(
                             "Enter the amount: ",
@@ -359,13 +375,14 @@ impl ClientNetworkNode {
             .iter()
             .map(|auction| {
                 format!(
-                    "{}\r\n \r\t Description: {} \r\n \r\t Start Price: {} € \r\n \r\t Goal Price: {} € \r\n  \r\t Current Bid: {} € \r\n \r\t State: {}",
+                    "{}\r\n \r\t Description: {} \r\n \r\t Start Price: {} € \r\n \r\t Goal Price: {} € \r\n  \r\t Current Bid: {} € \r\n \r\t State: {} \r\n \r\t Ends: {}",
                     auction.item.name,
                     auction.item.description,
                     auction.start_price,
                     auction.goal_price,
                     auction.current_price,
                     auction.get_state(),
+                    Self::format_deadline(auction),
                 )
             })
             .collect();
@@ -385,6 +402,22 @@ impl ClientNetworkNode {
         Ok(())
     }
 
+    fn format_deadline(auction: &Auction) -> String {
+        let ends = match auction.deadline {
+            None => return "When terminated by the seller".to_string(),
+            Some(Deadline::Height(height)) => format!("At block {}", height),
+            Some(Deadline::Timestamp(timestamp)) => DateTime::from_timestamp(timestamp, 0)
+                .map_or(timestamp.to_string(), |ends| {
+                    ends.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+                }),
+        };
+
+        match auction.extension_window {
+            Some(window) => format!("{} (extended by late bids, {} s)", ends, window),
+            None => ends,
+        }
+    }
+
     pub async fn view_create_auction(&self) -> Result<(), TermError> {
         let mut stdout = stdout();
         term::hide_cursor(true)?;
@@ -417,8 +450,38 @@ impl ClientNetworkNode {
                 Err(_) => continue,
             };
 
+            let duration = match CustomType::<u32>::new("Enter the duration in minutes: ")
+                .with_help_message("Leave empty for an auction you terminate yourself")
+                .prompt_skippable()
+            {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            let deadline = duration.map(|minutes| {
+                Deadline::Timestamp(Utc::now().timestamp() + i64::from(minutes) * 60)
+            });
+
+            // late bids only extend auctions that end on their own
+            let extension_window = match deadline {
+                Some(_) => match CustomType::<u32>::new("Enter the extension window in minutes: ")
+                    .with_help_message("Bids this close to the end extend it, leave empty for none")
+                    .prompt_skippable()
+                {
+                    Ok(value) => value.map(|minutes| i64::from(minutes) * 60),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+
             if let None = self
-                .create_auction(Item::new(name, description), start_price, goal_price)
+                .create_auction(
+                    Item::new(name, description),
+                    start_price,
+                    goal_price,
+                    deadline,
+                    extension_window,
+                )
                 .await
             {
                 term::println(
@@ -455,6 +518,15 @@ impl ClientNetworkNode {
                 return Ok(());
             };
 
+            term::println(
+                format!(
+                    "Hash rate: {:.0} H/s\n",
+                    self.network_node.pow_workers.hash_rate()
+                )
+                .as_str(),
+                style::Color::Grey,
+            )?;
+
             for block in block_chain_tx.search_blocks_on(|_| true) {
                 let mut color = style::Color::Magenta;
 
@@ -467,6 +539,10 @@ impl ClientNetworkNode {
                     format!("Merkle root: {}", hex::encode(block.header.merkle_root)).as_str(),
                     color,
                 )?;
+                term::println(
+                    format!("State root: {}", hex::encode(block.header.state_root)).as_str(),
+                    color,
+                )?;
                 term::println(format!("Nonce: {}", block.header.nonce).as_str(), color)?;
                 term::println(
                     format!("Hash: {}", hex::encode(block.header.hash)).as_str(),
@@ -1,7 +1,7 @@
 use std::any::Any;
 
 use crate::{
-    blockchain::{Block, BlockHeader},
+    blockchain::{Block, BlockHeader, Transaction},
     kademlia::data::KademliaData,
 };
 
@@ -16,6 +16,17 @@ impl KademliaData for BlockHeader {
     }
 }
 
+#[typetag::serde]
+impl KademliaData for Transaction {
+    fn clone_dyn(&self) -> Box<dyn KademliaData> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 #[typetag::serde]
 impl KademliaData for Block {
     fn clone_dyn(&self) -> Box<dyn KademliaData> {
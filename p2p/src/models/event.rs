@@ -4,8 +4,12 @@ use log::info;
 use tonic::async_trait;
 
 use crate::{
-    blockchain::{Block, BlockChainError, BlockChainEvent, BlockChainEventHandler, BlockHeader},
+    blockchain::{
+        Block, BlockChainError, BlockChainEvent, BlockChainEventHandler, BlockHeader, Transaction,
+        TransactionError, TransactionPoolError,
+    },
     kademlia::{
+        data::KademliaData,
         event::{DHTEvent, DHTEventHandler},
         NodeId,
     },
@@ -19,13 +23,13 @@ use super::network_node::NetworkNode;
 #[async_trait]
 impl DHTEventHandler for NetworkNode {
     async fn on_event(&self, event: DHTEvent) {
-        if let Err(_) = self.sync().await {
-            info!("Failed to sync with the network");
-            return;
-        }
-
         match event {
-            DHTEvent::Store(kademlia_data) => {
+            DHTEvent::Store(sender, kademlia_data) => {
+                if let Err(_) = self.sync().await {
+                    info!("Failed to sync with the network");
+                    return;
+                }
+
                 let check_block_filter =
                     if let Some(header) = kademlia_data.as_any().downcast_ref::<BlockHeader>() {
                         info!("Chain tip recived Recived! {:#?}", header);
@@ -48,43 +52,189 @@ impl DHTEventHandler for NetworkNode {
                     info!("Block Recived!");
                     let block_chain_tx = Arc::clone(&self.block_chain);
 
-                    {
+                    let append_result = {
                         let Ok(mut block_chain) = block_chain_tx.try_lock() else {
                             return;
                         };
 
-                        match block_chain.append_block(&block) {
-                            Ok(_) => {
-                                let block_key = NodeId::new(&block.header.hash);
-                                let block = block.clone();
-
-                                let kademlia = Arc::clone(&self.kademlia_net);
-                                tokio::spawn(async move {
-                                    if let Ok(kademlia) = kademlia.try_lock() {
-                                        let _ = kademlia.store(&block_key, Box::new(block)).await;
-                                        info!("Block repropagated to the network");
-                                    }
-                                });
-                            }
-                            Err(BlockChainError::ChainBroken) => {
-                                info!("Chain broken mate, fixing it...");
-                                self.fix_block_chain(&block.header).await;
-                            }
-                            Err(BlockChainError::InvalidBlock) => info!("invalid block"), // PoR - decrease peer's score
-                            Err(BlockChainError::BlockAlreadyPersisted) => {
-                                info!("Block already persisted")
-                            }
-                            Err(BlockChainError::BlockNotFound) => {
-                                info!("Failed to fetch block")
+                        let append_result = block_chain.append_block(&block);
+                        if append_result
+                            .as_ref()
+                            .is_err_and(BlockChainError::is_invalid_block)
+                            && block_chain.penalise_miner(&block)
+                        {
+                            info!(
+                                "Miner {} penalised, {} invalid blocks so far",
+                                hex::encode(block.header.miner),
+                                block_chain.penalties(&block.header.miner)
+                            );
+                        }
+
+                        append_result
+                    };
+
+                    match append_result {
+                        Ok(reorg) => {
+                            if let Some(reorg) = reorg {
+                                BlockChainEventHandler::on_event(self, reorg).await;
                             }
+
+                            let block_key = NodeId::new(&block.header.hash);
+                            let block = block.clone();
+
+                            let kademlia = Arc::clone(&self.kademlia_net);
+                            tokio::spawn(async move {
+                                if let Ok(kademlia) = kademlia.try_lock() {
+                                    let _ = kademlia.store(&block_key, Box::new(block)).await;
+                                    info!("Block repropagated to the network");
+                                }
+                            });
+                        }
+                        Err(BlockChainError::ChainBroken) => {
+                            info!("Orphan block received, requesting its parent...");
+                            self.request_missing_parent(&block.header).await;
+                        }
+                        Err(BlockChainError::InvalidBlock) => {
+                            info!(
+                                "Block {} is invalid, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            );
+
+                            self.penalise_peer(&sender).await;
+                        }
+                        Err(BlockChainError::InvalidSignature) => {
+                            info!(
+                                "Block {} has an invalid miner signature, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            );
+
+                            self.penalise_peer(&sender).await;
+                        }
+                        Err(BlockChainError::InvalidProofOfWork) => {
+                            info!(
+                                "Block {} doesn't meet the expected difficulty, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            );
+
+                            self.penalise_peer(&sender).await;
+                        }
+                        Err(BlockChainError::InvalidTransaction(id, e)) => {
+                            info!(
+                                "Block {} carries the invalid transaction {}: {}, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                hex::encode(id),
+                                e,
+                                sender.id
+                            );
+
+                            self.penalise_peer(&sender).await;
+                        }
+                        Err(BlockChainError::BlockAlreadyPersisted) => {
+                            info!("Block already persisted")
+                        }
+                        Err(BlockChainError::BlockNotFound) => {
+                            info!("Failed to fetch block")
+                        }
+                        Err(BlockChainError::IneligibleMiner) => {
+                            info!(
+                                "Block {} was signed by a miner that didn't win its slot, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            );
+
+                            self.penalise_peer(&sender).await;
+                        }
+                        Err(BlockChainError::StaleBlock) => info!("Block is stale"),
+                        Err(BlockChainError::FinalityViolation) => {
+                            info!(
+                                "Block {} would revert finalized history, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            )
+                        }
+                        Err(BlockChainError::InvalidTimestamp) => {
+                            info!(
+                                "Block {} has a timestamp out of range, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            )
+                        }
+                        Err(BlockChainError::InvalidStateRoot) => {
+                            info!(
+                                "Block {} commits to a state diverging from ours, sent by {:?}",
+                                hex::encode(block.header.hash),
+                                sender.id
+                            );
+
+                            self.penalise_peer(&sender).await;
                         }
                     }
                 }
 
                 self.persist_state().await;
             }
+            DHTEvent::Announce(sender, kademlia_data) => {
+                let Some(transaction) = kademlia_data.as_any().downcast_ref::<Transaction>() else {
+                    info!("Announced data is not a Transaction");
+                    return;
+                };
+
+                // waits for the lock, announces are handled in order and a
+                // dropped one would leave a gap in its sender's sequence
+                let submit_result = self
+                    .block_chain
+                    .lock()
+                    .await
+                    .submit_transaction(transaction.clone());
+
+                match submit_result {
+                    Ok(()) => {
+                        info!("Transaction received, announcing it...");
+                        self.announce_transaction(transaction).await;
+                    }
+                    // forwarded all the same, peers may already have the gap
+                    Err(e @ TransactionPoolError::Held(_)) => {
+                        info!("{}, announcing it...", e);
+                        self.announce_transaction(transaction).await;
+                    }
+                    Err(TransactionPoolError::InvalidTransaction(
+                        id,
+                        e @ (TransactionError::InvalidSignature
+                        | TransactionError::MissingFingerPrint),
+                    )) => {
+                        info!(
+                            "Transaction {} is invalid: {}, sent by {:?}",
+                            hex::encode(id),
+                            e,
+                            sender.id
+                        );
+
+                        self.penalise_peer(&sender).await;
+                    }
+                    Err(TransactionPoolError::Duplicate(_)) => {}
+                    Err(e) => info!("Announced transaction rejected: {}", e),
+                }
+            }
         }
     }
+
+    async fn pending(&self, limit: usize) -> Vec<Box<dyn KademliaData>> {
+        let block_chain = Arc::clone(&self.block_chain);
+        let Ok(block_chain) = block_chain.try_lock() else {
+            return vec![];
+        };
+
+        block_chain
+            .transaction_poll
+            .pending_transactions()
+            .into_iter()
+            .take(limit)
+            .map(|transaction| Box::new(transaction) as Box<dyn KademliaData>)
+            .collect()
+    }
 }
 
 impl NetworkNode {
@@ -129,30 +279,76 @@ impl NetworkNode {
         info!("Update Chain Head on network");
     }
 
+    /// Fetches the branch ending at `last_block` and appends it to the block
+    /// tree, which reorganises the chain if the branch carries more work.
     pub async fn fix_block_chain(&self, last_block: &BlockHeader) {
         let block_chain = Arc::clone(&self.block_chain);
         let last_key = NodeId::new(&last_block.hash);
 
         for incoming in self.fetch_block_chain(&last_key, MAX_TTL).await {
-            {
+            let append_result = {
                 let Ok(mut block_chain) = block_chain.try_lock() else {
                     info!("Failed to lock block chain");
-                    continue;
+                    return;
                 };
 
-                if let None = block_chain.remove_last() {
-                    info!("Failed to remove last block from the chain");
-                    continue;
+                block_chain.append_block(&incoming)
+            };
+
+            match append_result {
+                Ok(Some(reorg)) => BlockChainEventHandler::on_event(self, reorg).await,
+                Ok(None) | Err(BlockChainError::BlockAlreadyPersisted) => continue,
+                Err(e) => {
+                    info!("Failed to append fetched block: {}", e);
+                    return;
                 }
+            };
+        }
+    }
 
-                match block_chain.append_block(&incoming) {
-                    Ok(ok) => ok,
-                    _ => {
-                        info!("Failed to remove last block from the chain");
-                        continue;
-                    }
+    /// Requests, one at a time, the parents missing below the `orphan` block
+    /// until its sub-chain gets connected to the block tree.
+    pub async fn request_missing_parent(&self, orphan: &BlockHeader) {
+        let block_chain = Arc::clone(&self.block_chain);
+        let mut orphan_hash = orphan.hash;
+
+        for _ in 0..MAX_TTL {
+            let missing = {
+                let Ok(block_chain) = block_chain.try_lock() else {
+                    info!("Failed to lock block chain");
+                    return;
                 };
+
+                block_chain.missing_parent(&orphan_hash)
+            };
+
+            let Some(missing) = missing else {
+                return;
+            };
+
+            let Some(parent) = self.search_for_block(&NodeId::new(&missing)).await else {
+                info!("Failed to fetch missing parent {}", hex::encode(missing));
+                return;
             };
+
+            let append_result = {
+                let Ok(mut block_chain) = block_chain.try_lock() else {
+                    info!("Failed to lock block chain");
+                    return;
+                };
+
+                block_chain.append_block(&parent)
+            };
+
+            match append_result {
+                Ok(Some(reorg)) => return BlockChainEventHandler::on_event(self, reorg).await,
+                Ok(None) | Err(BlockChainError::BlockAlreadyPersisted) => return,
+                Err(BlockChainError::ChainBroken) => orphan_hash = parent.header.hash,
+                Err(e) => {
+                    info!("Failed to append missing parent: {}", e);
+                    return;
+                }
+            }
         }
     }
 }
@@ -160,26 +356,27 @@ impl NetworkNode {
 #[async_trait]
 impl BlockChainEventHandler for NetworkNode {
     async fn on_event(&self, event: BlockChainEvent) {
-        if let Err(_) = self.sync().await {
-            info!("Failed to sync with the network");
-            return;
-        }
-
         match event {
             BlockChainEvent::AddBlock(block) => {
+                if let Err(_) = self.sync().await {
+                    info!("Failed to sync with the network");
+                    return;
+                }
+
                 let block_key = NodeId::new(&block.header.hash);
 
-                let last_block = {
-                    let Some(last_block) = self.fetch_last_block_header(block.clone()).await else {
+                let is_head = {
+                    let block_chain = Arc::clone(&self.block_chain);
+                    let Ok(block_chain) = block_chain.try_lock() else {
                         return;
                     };
 
-                    last_block
+                    block_chain
+                        .get_blockchain_head()
+                        .is_some_and(|head| head.header.hash == block.header.hash)
                 };
 
-                if last_block.hash != block.header.hash {
-                    self.fix_block_chain(&last_block).await;
-                } else {
+                if is_head {
                     self.update_global_bc_head(&block.header).await;
                 }
 
@@ -189,7 +386,7 @@ impl BlockChainEventHandler for NetworkNode {
                         return;
                     };
 
-                    kademlia.store(&block_key, Box::new(block.clone())).await
+                    kademlia.store(&block_key, block.clone()).await
                 };
 
                 if let Err(_) = propagate_block {
@@ -202,6 +399,15 @@ impl BlockChainEventHandler for NetworkNode {
 
                 info!("Propagating block...")
             }
+            BlockChainEvent::Reorg { removed, added } => {
+                info!(
+                    "[🔀] Chain reorganised: {} blocks removed, {} blocks added",
+                    removed.len(),
+                    added.len()
+                );
+
+                self.persist_state().await;
+            }
         }
     }
 }
@@ -1,7 +1,7 @@
 use std::{
     collections::HashSet,
     error::Error,
-    sync::Arc,
+    sync::{atomic::AtomicBool, Arc},
     time::{self, Duration},
 };
 
@@ -13,8 +13,14 @@ use rand::{
 use tokio::sync::Mutex;
 
 use crate::{
-    blockchain::{Block, BlockChain, BlockChainError, BlockChainEventHandler, BlockHeader},
-    kademlia::{event::DHTEventHandler, node::Contract, NodeId},
+    blockchain::{
+        Block, BlockChain, BlockChainEventHandler, BlockHeader, ChainParams, ConsensusKind,
+        MinerRegistration, PowWorkers, ProofOfReputation, Transaction, TransactionPoolError,
+    },
+    kademlia::{
+        dht::MAX_PENDING_FETCH, event::DHTEventHandler, node::Contract, secret_key::SecretPair,
+        NodeId,
+    },
     DHTNode, Node,
 };
 
@@ -26,12 +32,17 @@ pub struct NetworkMode {
     pub bootstraps: Vec<Contract>,
     pub host: String,
     pub port: usize,
+    pub chain_params: ChainParams,
+    pub miner_threads: usize,
 }
 
 #[derive(Debug)]
 pub struct NetworkNode {
     pub block_chain: Arc<Mutex<BlockChain>>,
     pub kademlia_net: Arc<Mutex<DHTNode>>,
+
+    /// Proof of work search shared by the miner and the join challenge.
+    pub pow_workers: PowWorkers,
 }
 
 impl NetworkNode {
@@ -46,6 +57,7 @@ impl NetworkNode {
         let network_node = Self {
             block_chain,
             kademlia_net: Arc::clone(&dht),
+            pow_workers: PowWorkers::new(mode.miner_threads),
         };
 
         let network_node = Arc::new(network_node);
@@ -78,7 +90,11 @@ impl NetworkNode {
                     continue;
                 };
 
-                if kademlia.join_network(&bootstrap).await.is_some() {
+                if kademlia
+                    .join_network(&bootstrap, &self.pow_workers)
+                    .await
+                    .is_some()
+                {
                     break;
                 }
             }
@@ -93,6 +109,8 @@ impl NetworkNode {
             return;
         }
 
+        self.sync_transactions().await;
+
         println!("Syncing process completed!");
     }
 
@@ -101,7 +119,8 @@ impl NetworkNode {
             return None;
         };
 
-        Self::load_from(mode, BlockChain::new(), dht).await
+        let block_chain = BlockChain::with_params(mode.chain_params.clone());
+        Self::load_from(mode, block_chain, dht).await
     }
 
     pub(crate) async fn connect(self: Arc<Self>) {
@@ -121,12 +140,86 @@ impl NetworkNode {
             Arc::clone(&self) as Arc<dyn BlockChainEventHandler>;
 
         BlockChain::start_miner(
-            node_key_pair,
+            node_key_pair.clone(),
+            self.pow_workers.clone(),
             self.block_chain.clone(),
             handler,
             BATCH_PULLING_SIZE,
             BATCH_PULLING_TIME_FRAME,
         );
+
+        Self::register_miner(self, node_key_pair);
+    }
+
+    /// Registers the node's key as a miner whenever it isn't eligible under
+    /// proof of reputation, so it may take part in the block lottery.
+    pub(crate) fn register_miner(network_node: Arc<NetworkNode>, pair: SecretPair) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(BATCH_PULLING_TIME_FRAME).await;
+
+                let difficulty = {
+                    let Ok(block_chain) = network_node.block_chain.try_lock() else {
+                        continue;
+                    };
+
+                    if block_chain.params().consensus != ConsensusKind::ProofOfReputation {
+                        return;
+                    }
+
+                    let pending = block_chain
+                        .transaction_poll
+                        .pending_for(&pair.public_key)
+                        .iter()
+                        .any(|transaction| transaction.get_data::<MinerRegistration>().is_some());
+
+                    if pending || ProofOfReputation::is_eligible(&block_chain, &pair.public_key) {
+                        continue;
+                    }
+
+                    block_chain.params().registration_difficulty
+                };
+
+                info!("[⛏️] Registering as a miner...");
+                let registration = {
+                    let workers = network_node.pow_workers.clone();
+                    let miner = pair.public_key;
+                    tokio::task::spawn_blocking(move || {
+                        MinerRegistration::solve(
+                            &miner,
+                            difficulty,
+                            &workers,
+                            &AtomicBool::new(false),
+                        )
+                    })
+                    .await
+                };
+
+                let Ok(Some(registration)) = registration else {
+                    info!("Failed to register as a miner");
+                    continue;
+                };
+
+                let transaction = {
+                    let block_chain = network_node.block_chain.lock().await;
+                    let nonce = block_chain.next_nonce(&pair.public_key);
+
+                    let Some(transaction) = Transaction::new(pair.clone(), nonce, registration)
+                    else {
+                        continue;
+                    };
+
+                    if let Err(e) = block_chain.submit_transaction(transaction.clone()) {
+                        info!("Failed to submit the miner registration: {}", e);
+                        continue;
+                    }
+
+                    transaction
+                };
+
+                network_node.announce_transaction(&transaction).await;
+            }
+        });
     }
 
     pub(crate) fn check_peers_health(network_node: Arc<NetworkNode>) {
@@ -188,6 +281,77 @@ impl NetworkNode {
         });
     }
 
+    pub(crate) async fn penalise_peer(&self, peer: &Node) {
+        let kademlia_net = Arc::clone(&self.kademlia_net);
+        let Ok(kademlia) = kademlia_net.try_lock() else {
+            return;
+        };
+
+        let routing_table = Arc::clone(&kademlia.routing_table);
+        if let Ok(mut routing_table) = routing_table.try_lock() {
+            info!("Removing misbehaving node: {:#?}", peer);
+            routing_table.remove(peer);
+        };
+    }
+
+    pub async fn announce_transaction(&self, transaction: &Transaction) {
+        let kademlia_net = Arc::clone(&self.kademlia_net);
+        let transaction = transaction.clone();
+
+        // a gossip round waits on every neighbour, so it runs off the caller's
+        // task and waits its turn, the peers only sync their pool at startup
+        tokio::spawn(async move {
+            let kademlia = kademlia_net.lock().await;
+            let id = transaction.id();
+
+            if let Err(e) = kademlia.announce(Box::new(transaction)).await {
+                info!("Dropped announce of transaction {}: {}", hex::encode(id), e);
+            }
+        });
+    }
+
+    /// Pulls the transactions pending on the neighbours into the local pool.
+    pub async fn sync_transactions(&self) {
+        let pending = {
+            let kademlia_net = Arc::clone(&self.kademlia_net);
+            let Ok(kademlia) = kademlia_net.try_lock() else {
+                return;
+            };
+
+            match kademlia.fetch_pending(MAX_PENDING_FETCH).await {
+                Ok(pending) => pending,
+                Err(e) => {
+                    info!("Failed to fetch pending transactions: {}", e);
+                    return;
+                }
+            }
+        };
+
+        let mut transactions = pending
+            .iter()
+            .filter_map(|value| value.as_any().downcast_ref::<Transaction>())
+            .collect::<Vec<_>>();
+
+        // every sender's transactions must be admitted in sequence
+        transactions.sort_by_key(|transaction| transaction.nonce);
+
+        let block_chain = Arc::clone(&self.block_chain);
+        let Ok(block_chain) = block_chain.try_lock() else {
+            return;
+        };
+
+        let mut admitted = 0;
+        for transaction in transactions {
+            match block_chain.submit_transaction(transaction.clone()) {
+                Ok(()) => admitted += 1,
+                Err(TransactionPoolError::Duplicate(_) | TransactionPoolError::Held(_)) => {}
+                Err(e) => info!("Pending transaction rejected: {}", e),
+            }
+        }
+
+        info!("Synced {} pending transactions", admitted);
+    }
+
     pub async fn search_for_block(&self, block_hash: &NodeId) -> Option<Block> {
         let kademlia_net = Arc::clone(&self.kademlia_net);
         let fetch_block = {
@@ -216,6 +380,8 @@ impl NetworkNode {
         None
     }
 
+    /// Walks back from `search_block_hash` until reaching a block already known
+    /// locally, returning the missing branch from its oldest block.
     pub async fn fetch_block_chain(
         &self,
         search_block_hash: &NodeId,
@@ -226,18 +392,6 @@ impl NetworkNode {
         let mut visited = HashSet::new();
         let block_chain = Arc::clone(&self.block_chain);
 
-        let goal_block = {
-            let Ok(block_chain) = block_chain.try_lock() else {
-                return vec![].into_iter();
-            };
-
-            block_chain.get_blockchain_head().cloned()
-        };
-
-        let Some(goal_block) = goal_block else {
-            return vec![].into_iter();
-        };
-
         let Some(mut block) = self.search_for_block(search_block_hash).await else {
             return Vec::new().into_iter();
         };
@@ -251,8 +405,14 @@ impl NetworkNode {
 
             founded_blocks.push(block.clone());
 
-            if goal_block.header.hash == block.header.prev_hash {
-                break;
+            {
+                let Ok(block_chain) = block_chain.try_lock() else {
+                    break;
+                };
+
+                if block_chain.contains(&block.header.prev_hash) {
+                    break;
+                }
             }
 
             let prev_hash = NodeId::new(&block.header.prev_hash);
@@ -270,45 +430,32 @@ impl NetworkNode {
     }
 
     pub async fn sync(&self) -> Result<(), ()> {
-        let fetch_chain_head = {
-            let block_chain = Arc::clone(&self.block_chain);
-            let Ok(block_chain) = block_chain.try_lock() else {
-                return Err(());
-            };
-
-            block_chain.get_blockchain_head().cloned()
-        };
-
-        let Some(chain_head) = fetch_chain_head else {
-            return Err(());
-        };
-
-        let Some(last_block) = self.fetch_last_block_header(chain_head.clone()).await else {
+        let Some(chain_heads) = self.fetch_chain_heads().await else {
             return Err(());
         };
 
-        let search_key = NodeId::new(&last_block.hash);
-        for block in self.fetch_block_chain(&search_key, MAX_TTL).await {
-            let block_chain = Arc::clone(&self.block_chain);
-            {
-                let Ok(mut block_chain) = block_chain.try_lock() else {
+        for chain_head in chain_heads {
+            let is_known = {
+                let block_chain = Arc::clone(&self.block_chain);
+                let Ok(block_chain) = block_chain.try_lock() else {
                     return Err(());
                 };
 
-                match block_chain.append_block(&block) {
-                    Ok(_) | Err(BlockChainError::BlockAlreadyPersisted) => continue,
-                    Err(_) => {}
-                }
+                block_chain.contains(&chain_head.hash)
             };
 
-            self.fix_block_chain(&block.header).await;
+            if !is_known {
+                self.fix_block_chain(&chain_head).await;
+            }
         }
 
         Ok(())
     }
 
-    pub async fn fetch_last_block_header(&self, tip: Block) -> Option<BlockHeader> {
-        let mut candidate_blocks = vec![tip.header];
+    /// Chain heads announced by the closest nodes, the block tree decides which
+    /// one becomes canonical once their branches are fetched.
+    pub async fn fetch_chain_heads(&self) -> Option<Vec<BlockHeader>> {
+        let mut chain_heads = vec![];
         let kademlia_net = Arc::clone(&self.kademlia_net);
 
         let closest_nodes = {
@@ -329,7 +476,6 @@ impl NetworkNode {
             return None;
         };
 
-        info!("OF MOST NODES: {:#?}", closest_nodes);
         while let Some(search_node) = closest_nodes.pop() {
             let search_key = NodeId::create_chain_head(search_node.id.clone());
 
@@ -338,17 +484,14 @@ impl NetworkNode {
                     continue;
                 };
 
-                let block_header = match kademlia.find_value(&search_key).await {
+                match kademlia.find_value(&search_key).await {
                     Ok(result) => result,
                     _ => {
                         continue;
                     }
-                };
-
-                block_header
+                }
             };
 
-            info!("OF MOST FOUND: {:#?}", block_header);
             let Some(block) = block_header else {
                 continue;
             };
@@ -361,15 +504,10 @@ impl NetworkNode {
                 continue;
             }
 
-            candidate_blocks.push(block.clone());
+            chain_heads.push(block.clone());
         }
 
-        candidate_blocks.into_iter().max_by(|a, b| {
-            a.difficulty
-                .cmp(&b.difficulty)
-                .then_with(|| a.index.cmp(&b.index))
-                .then_with(|| b.timestamp.cmp(&a.timestamp))
-        })
+        Some(chain_heads)
     }
 
     pub async fn get_connection(&self) -> Result<Node, Box<dyn Error + '_>> {
@@ -1,13 +1,14 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
+use log::{error, info};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    blockchain::{BlockChain, DoubleHasher},
+    blockchain::{BlockChain, DoubleHasher, LegacyBlockChain},
     kademlia::store::PersistDHTNode,
-    store::NetworkNodeStorage,
-    DHTNode,
+    store::{NetworkNodeStorage, StoreError},
+    DHTNode, Node,
 };
 
 use super::network_node::{NetworkMode, NetworkNode};
@@ -18,12 +19,50 @@ pub enum StoreNodeError {
     PersistError,
 }
 
+/// Bumped whenever the persisted layout changes, along with a migration from
+/// the previous one.
+pub const PERSIST_VERSION: u32 = 1;
+
+/// Prefix of every versioned layout.
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistVersion {
+    version: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PersistNodeNetwork {
+    version: u32,
     block_chain: BlockChain,
     dht: PersistDHTNode,
 }
 
+/// Layout written before the persisted state was versioned. Only the prefix up
+/// to the node identity is decoded, the DHT values cached after it used the old
+/// block layout and are fetched again from the network.
+#[derive(Debug, Serialize, Deserialize)]
+struct LegacyPersistNodeNetwork {
+    block_chain: LegacyBlockChain,
+    dht: LegacyPersistDHTNode,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LegacyPersistDHTNode {
+    core: Node,
+}
+
+impl From<LegacyPersistNodeNetwork> for PersistNodeNetwork {
+    fn from(legacy: LegacyPersistNodeNetwork) -> Self {
+        PersistNodeNetwork {
+            version: PERSIST_VERSION,
+            block_chain: legacy.block_chain.into(),
+            dht: PersistDHTNode {
+                core: legacy.dht.core,
+                distributed_hash_tb: HashMap::new(),
+            },
+        }
+    }
+}
+
 impl NetworkNode {
     pub async fn persist_node(
         &self,
@@ -51,6 +90,7 @@ impl NetworkNode {
             };
 
             Ok(PersistNodeNetwork {
+                version: PERSIST_VERSION,
                 block_chain: block_chain.clone(),
                 dht,
             })
@@ -67,22 +107,41 @@ impl NetworkNode {
         mode: NetworkMode,
         storage: impl NetworkNodeStorage,
     ) -> Option<Arc<Self>> {
-        let persist_node = {
-            match storage.load::<PersistNodeNetwork>() {
-                Ok(persist) => persist,
-                Err(_) => {
-                    let Some(persist_dht) = PersistDHTNode::new() else {
-                        panic!("Couldn't create a node")
-                    };
-
-                    PersistNodeNetwork {
-                        block_chain: BlockChain::new(),
-                        dht: persist_dht,
-                    }
-                }
+        // the legacy layout has no version, its fixed difficulty is read instead
+        let persist_node = match storage.load::<PersistVersion>() {
+            Err(StoreError::NotFound) => {
+                let Some(persist_dht) = PersistDHTNode::new() else {
+                    panic!("Couldn't create a node")
+                };
+
+                Some(PersistNodeNetwork {
+                    version: PERSIST_VERSION,
+                    block_chain: BlockChain::with_params(mode.chain_params.clone()),
+                    dht: persist_dht,
+                })
             }
+            Ok(PersistVersion {
+                version: PERSIST_VERSION,
+            }) => storage.load::<PersistNodeNetwork>().ok(),
+            _ => storage
+                .load::<LegacyPersistNodeNetwork>()
+                .ok()
+                .map(|legacy| {
+                    info!("Migrating node state from the legacy layout");
+                    legacy.into()
+                }),
         };
 
+        // starting over would overwrite the stored chain on the next persist
+        let Some(mut persist_node) = persist_node else {
+            error!("Stored node state can't be decoded, refusing to start over an empty chain");
+            return None;
+        };
+
+        persist_node
+            .block_chain
+            .set_params(mode.chain_params.clone());
+
         if !persist_node.block_chain.validate(DoubleHasher {}) {
             return None;
         }
@@ -1,18 +1,34 @@
 use std::any::Any;
 
+use log::error;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::blockchain::{DoubleHasher, HashFunc, TransactionData};
+use crate::blockchain::{
+    BlockHeader, DoubleHasher, HashFunc, StateTransition, Transaction, TransactionData,
+    TransactionError, WorldState,
+};
 
-use super::auctions::{item::Item, Currency, PublicKey};
+use super::auctions::{
+    auction::{Auction, AuctionError, Deadline},
+    bid::Bid,
+    block_timestamp,
+    item::Item,
+    Currency, PublicKey, Timestamp,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct CreateAuction {
     pub id: Uuid,
     pub item: Item,
     pub start_price: Currency,
     pub goal_price: Currency,
+
+    /// Closes the auction without the seller, see [`Deadline`].
+    pub deadline: Option<Deadline>,
+
+    /// Anti-sniping window in seconds, see [`Auction::extension_window`].
+    pub extension_window: Option<Timestamp>,
 }
 
 impl CreateAuction {
@@ -22,6 +38,100 @@ impl CreateAuction {
             item,
             start_price,
             goal_price,
+            deadline: None,
+            extension_window: None,
+        }
+    }
+
+    pub fn with_deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Only valid along with a [`Deadline::Timestamp`].
+    pub fn with_extension_window(mut self, seconds: Timestamp) -> Self {
+        self.extension_window = Some(seconds);
+        self
+    }
+}
+
+/// Fields of [`CreateAuction`] signed before deadlines existed.
+#[derive(Serialize, Deserialize)]
+struct CreateAuctionFields {
+    id: Uuid,
+    item: Item,
+    start_price: Currency,
+    goal_price: Currency,
+}
+
+/// Encoding of [`AuctionTransaction`], where [`CreateAuction`] options are new
+/// variants so the transactions signed before them keep their encoding and hash.
+#[derive(Serialize, Deserialize)]
+enum AuctionTransactionRepr {
+    Create(CreateAuctionFields),
+    Bid(PlaceBid),
+    Cancel(CancelAuction),
+    End(EndAuction),
+    CreateWithDeadline(CreateAuctionFields, Deadline),
+    CreateWithExtension(CreateAuctionFields, Option<Deadline>, Timestamp),
+}
+
+impl From<AuctionTransaction> for AuctionTransactionRepr {
+    fn from(transaction: AuctionTransaction) -> Self {
+        match transaction {
+            AuctionTransaction::Create(create_auction) => {
+                let fields = CreateAuctionFields {
+                    id: create_auction.id,
+                    item: create_auction.item,
+                    start_price: create_auction.start_price,
+                    goal_price: create_auction.goal_price,
+                };
+
+                match (create_auction.deadline, create_auction.extension_window) {
+                    (deadline, Some(window)) => {
+                        AuctionTransactionRepr::CreateWithExtension(fields, deadline, window)
+                    }
+                    (Some(deadline), None) => {
+                        AuctionTransactionRepr::CreateWithDeadline(fields, deadline)
+                    }
+                    (None, None) => AuctionTransactionRepr::Create(fields),
+                }
+            }
+            AuctionTransaction::Bid(place_bid) => AuctionTransactionRepr::Bid(place_bid),
+            AuctionTransaction::Cancel(cancel_auction) => {
+                AuctionTransactionRepr::Cancel(cancel_auction)
+            }
+            AuctionTransaction::End(end_auction) => AuctionTransactionRepr::End(end_auction),
+        }
+    }
+}
+
+impl From<AuctionTransactionRepr> for AuctionTransaction {
+    fn from(repr: AuctionTransactionRepr) -> Self {
+        let create = |fields: CreateAuctionFields, deadline, extension_window| {
+            AuctionTransaction::Create(CreateAuction {
+                id: fields.id,
+                item: fields.item,
+                start_price: fields.start_price,
+                goal_price: fields.goal_price,
+                deadline,
+                extension_window,
+            })
+        };
+
+        match repr {
+            AuctionTransactionRepr::Create(fields) => create(fields, None, None),
+            AuctionTransactionRepr::Bid(place_bid) => AuctionTransaction::Bid(place_bid),
+            AuctionTransactionRepr::Cancel(cancel_auction) => {
+                AuctionTransaction::Cancel(cancel_auction)
+            }
+            AuctionTransactionRepr::End(end_auction) => AuctionTransaction::End(end_auction),
+            AuctionTransactionRepr::CreateWithDeadline(fields, deadline) => {
+                create(fields, Some(deadline), None)
+            }
+            AuctionTransactionRepr::CreateWithExtension(fields, deadline, window) => {
+                create(fields, deadline, Some(window))
+            }
         }
     }
 }
@@ -66,6 +176,7 @@ impl EndAuction {
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
+#[serde(from = "AuctionTransactionRepr", into = "AuctionTransactionRepr")]
 pub enum AuctionTransaction {
     Create(CreateAuction),
     Bid(PlaceBid),
@@ -73,6 +184,17 @@ pub enum AuctionTransaction {
     End(EndAuction),
 }
 
+impl AuctionTransaction {
+    pub fn auction_id(&self) -> Uuid {
+        match self {
+            AuctionTransaction::Create(create_auction) => create_auction.id,
+            AuctionTransaction::Bid(place_bid) => place_bid.auction_id,
+            AuctionTransaction::Cancel(cancel_auction) => cancel_auction.auction_id,
+            AuctionTransaction::End(end_auction) => end_auction.auction_id,
+        }
+    }
+}
+
 #[typetag::serde]
 impl TransactionData for AuctionTransaction {
     fn get_hash(&self) -> Option<PublicKey> {
@@ -93,4 +215,156 @@ impl TransactionData for AuctionTransaction {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    /// Deadlines are checked against the block carrying the transaction, which
+    /// may come long after its parent.
+    fn validate(
+        &self,
+        header: &BlockHeader,
+        transaction: &Transaction,
+        state: &WorldState,
+    ) -> Result<(), TransactionError> {
+        let height = header.index;
+        let timestamp = block_timestamp(header.timestamp);
+
+        let auction = state.get_as::<Auction>(&Auction::state_key(&self.auction_id()));
+
+        let checked = match (self, auction) {
+            (AuctionTransaction::Create(create_auction), None) => {
+                match (create_auction.deadline, create_auction.extension_window) {
+                    (Some(deadline), _) if deadline.is_passed_by(height, timestamp) => {
+                        Err(AuctionError::DeadlinePassed)
+                    }
+                    (None | Some(Deadline::Height(_)), Some(_)) => {
+                        Err(AuctionError::ExtensionWithoutDeadline)
+                    }
+                    (_, Some(window)) if window <= 0 => Err(AuctionError::InvalidExtensionWindow),
+                    _ => Ok(()),
+                }
+            }
+            (AuctionTransaction::Create(_), Some(_)) => Err(AuctionError::AlreadyExists),
+            (_, None) => Err(AuctionError::UnknownAuction),
+            (_, Some(auction)) if auction.is_due(height, timestamp) => Err(AuctionError::Closed),
+            (AuctionTransaction::Bid(place_bid), Some(auction)) => {
+                auction.check_bid(&transaction.from, place_bid.amount)
+            }
+            (AuctionTransaction::Cancel(_) | AuctionTransaction::End(_), Some(auction)) => {
+                auction.check_close(&transaction.from)
+            }
+        };
+
+        checked.map_err(|e| TransactionError::Rejected(Box::new(e)))
+    }
+
+    /// Operations on unknown auctions are ignored, as are the bids the auction
+    /// itself turns down, which blocks before validation could carry.
+    fn apply(
+        &self,
+        header: &BlockHeader,
+        transaction: &Transaction,
+        state: &mut StateTransition,
+    ) -> Result<(), TransactionError> {
+        // the block timestamp, unlike the local clock, is the same on every node
+        let timestamp = block_timestamp(header.timestamp);
+
+        let key = Auction::state_key(&self.auction_id());
+        let auction = match self {
+            AuctionTransaction::Create(create_auction) => Auction::new(
+                create_auction.id,
+                transaction.from,
+                create_auction.item.clone(),
+                create_auction.start_price,
+                create_auction.goal_price,
+                timestamp,
+                create_auction.deadline,
+            )
+            .with_extension_window(create_auction.extension_window),
+            _ => {
+                let Some(mut auction) = state.get_as::<Auction>(&key) else {
+                    return Ok(());
+                };
+
+                match self {
+                    AuctionTransaction::Bid(place_bid) => auction.add_bid(Bid::new(
+                        place_bid.id,
+                        transaction.from,
+                        place_bid.auction_id,
+                        place_bid.amount,
+                        timestamp,
+                    )),
+                    AuctionTransaction::Cancel(_) => auction.cancel(timestamp),
+                    AuctionTransaction::End(_) => auction.terminate(timestamp),
+                    AuctionTransaction::Create(_) => unreachable!(),
+                }
+
+                auction
+            }
+        };
+
+        if state.put_as(key, &auction).is_none() {
+            error!("Failed to store auction {}", auction.id);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockchain::{Block, TransactionError, WorldState},
+        kademlia::secret_key::SecretPair,
+        models::auctions::{auction::Deadline, item::Item},
+    };
+
+    const NANOS: u128 = 1_000_000_000;
+
+    fn header(index: u64, seconds: u128) -> BlockHeader {
+        Block::new(
+            index,
+            0,
+            [0; 32],
+            [0; 32],
+            seconds * NANOS,
+            0,
+            [0; 32],
+            vec![],
+        )
+        .header
+    }
+
+    #[test]
+    fn bid_in_block_stamped_after_deadline_is_rejected() {
+        let seller = SecretPair::generate_keys().expect("Failed to generate keys");
+        let buyer = SecretPair::generate_keys().expect("Failed to generate keys");
+
+        let mut create = CreateAuction::new(Item::new("item".into(), "".into()), 10, 100);
+        create.deadline = Some(Deadline::Timestamp(1_000));
+        let auction_id = create.id;
+
+        let mut state = WorldState::default();
+        let create = Transaction::new(seller, 0, AuctionTransaction::Create(create))
+            .expect("Failed to sign transaction");
+        state
+            .apply_transaction(&header(1, 900), &create)
+            .expect("Auction is created");
+
+        let bid = Transaction::new(
+            buyer,
+            0,
+            AuctionTransaction::Bid(PlaceBid::new(auction_id, 20)),
+        )
+        .expect("Failed to sign transaction");
+
+        // the parent block is still before the deadline, the one carrying the bid isn't
+        for late in [1_000, 5_000] {
+            assert!(matches!(
+                state.apply_transaction(&header(2, late), &bid),
+                Err(TransactionError::Rejected(_))
+            ));
+        }
+
+        assert!(state.apply_transaction(&header(2, 999), &bid).is_ok());
+    }
 }